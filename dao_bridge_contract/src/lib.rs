@@ -3,7 +3,7 @@
 
 use soroban_auth::{Identifier, Signature};
 use soroban_sdk::{
-    contractimpl, contracttype, Env, BytesN, contracterror, panic_with_error
+    contractimpl, contracttype, Env, BytesN, contracterror, panic_with_error, Address
 };
 mod dao_token;
 mod token {
@@ -24,15 +24,25 @@ pub enum DataKey {
     PowerOf(Identifier),
     DelegatTo(Identifier),
     CanMint(BytesN<32>),
-
+    Version,
+    // the account that initialized the bridge, the only one allowed to migrate it
+    Admin,
+    // the token the bridge was initialized with
+    TokenId,
 }
 
+// Bump together with a new step in `migrate` whenever stored data changes shape.
+// Replacing the deployed wasm isn't possible on soroban-sdk 0.4.3, see the README.
+const CONTRACT_VERSION: u32 = 1;
+
 #[contracterror]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 #[repr(u32)]
 pub enum Error {
     CannotMint = 1,
     InsufficientPower = 2,
+    AlreadyInitialized = 3,
+    NotAuthorized = 4,
 }
 
 
@@ -42,6 +52,12 @@ pub trait DaoBridgeTrait {
     // mint without creating proposal. 
     // The [signature] should have enough power to mint.
     fn fast_mint(env: Env, reference: BytesN<32>, recipient: Identifier, amount: i128, token_id: BytesN<32>, signature: Signature, nonce: i128);
+    // brings storage written by an older version up to CONTRACT_VERSION.
+    // Each step runs once; calling it again is a no-op.
+    // Only the account that called init may invoke it. A v0 bridge recorded
+    // no admin, so whoever migrates it first becomes its admin.
+    fn migrate(env: Env);
+    fn version(env: Env) -> u32;
 }
 
 pub struct DaoBridgeContract;
@@ -49,8 +65,13 @@ pub struct DaoBridgeContract;
 #[contractimpl]
 impl DaoBridgeTrait for DaoBridgeContract {
     fn init(env: Env, token_id: BytesN<32>){
+        if has_admin(&env) || is_versioned(&env) || has_token(&env) || can_mint(&env, token_id.clone()){
+            panic_with_error!(&env, Error::AlreadyInitialized);
+        }
+        write_admin(&env, env.invoker());
+        write_token(&env, token_id.clone());
         approve_token_minting(&env, token_id);
-        
+        write_version(&env, CONTRACT_VERSION);
     }
 
     fn fast_mint(env: Env, reference: BytesN<32>, recipient: Identifier, amount: i128, token_id: BytesN<32>, signature: Signature, nonce: i128){
@@ -65,6 +86,84 @@ impl DaoBridgeTrait for DaoBridgeContract {
         mint(&env, recipient, amount, token_id);
     }
 
+    fn migrate(env: Env){
+        if is_v0(&env){
+            write_admin(&env, env.invoker());
+        }
+        check_admin(&env);
+        let from = read_version(&env);
+        if from >= CONTRACT_VERSION {
+            return;
+        }
+        // v0 -> v1 only starts tracking the version, nothing to move yet.
+        write_version(&env, CONTRACT_VERSION);
+    }
+
+    fn version(env: Env) -> u32{
+        read_version(&env)
+    }
+
+}
+
+fn has_admin(env: &Env) -> bool{
+    env
+    .storage()
+    .has(DataKey::Admin)
+}
+
+// a bridge deployed before versioning has neither an admin nor a version.
+fn is_v0(env: &Env) -> bool{
+    !has_admin(env) && !is_versioned(env)
+}
+
+fn is_versioned(env: &Env) -> bool{
+    env
+    .storage()
+    .has(DataKey::Version)
+}
+
+fn has_token(env: &Env) -> bool{
+    env
+    .storage()
+    .has(DataKey::TokenId)
+}
+
+fn write_token(env: &Env, token_id: BytesN<32>){
+    env
+    .storage()
+    .set(DataKey::TokenId, token_id);
+}
+
+fn write_admin(env: &Env, admin: Address){
+    env
+    .storage()
+    .set(DataKey::Admin, admin);
+}
+
+// panics unless the invoker is the admin.
+fn check_admin(env: &Env){
+    let admin: Option<Address> = env
+    .storage()
+    .get(DataKey::Admin)
+    .map(|admin| admin.unwrap());
+    if admin != Some(env.invoker()){
+        panic_with_error!(env, Error::NotAuthorized);
+    }
+}
+
+// contracts deployed before the version key existed read as version 0.
+fn read_version(env: &Env) -> u32{
+    env
+    .storage()
+    .get(DataKey::Version)
+    .unwrap_or(Ok(0))
+    .unwrap()
+}
+
+fn write_version(env: &Env, version: u32){
+    env
+    .storage()
+    .set(DataKey::Version, version);
 }
 
 fn approve_token_minting(env: &Env, token_id: BytesN<32>){
@@ -101,5 +200,5 @@ fn can_mint(env: &Env, token_id: BytesN<32>) -> bool{
     .unwrap()
 }
 
-// #[cfg(test)]
-// mod test;
\ No newline at end of file
+#[cfg(test)]
+mod test;
\ No newline at end of file
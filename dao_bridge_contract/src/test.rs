@@ -0,0 +1,65 @@
+#![cfg(test)]
+extern crate std;
+
+use crate::{DaoBridgeContract, DaoBridgeContractClient, DataKey, Error, CONTRACT_VERSION};
+use soroban_sdk::testutils::Accounts;
+use soroban_sdk::{AccountId, BytesN, Env};
+
+fn setup(e: &Env) -> (BytesN<32>, DaoBridgeContractClient, AccountId) {
+    let contract_id = e.register_contract(None, DaoBridgeContract);
+    let client = DaoBridgeContractClient::new(e, &contract_id);
+    let admin = e.accounts().generate_and_create();
+    e.set_source_account(&admin);
+    client.init(&BytesN::from_array(e, &[1; 32]));
+    (contract_id, client, admin)
+}
+
+#[test]
+fn test_version() {
+    let e: Env = Default::default();
+    let (_, client, _) = setup(&e);
+    assert_eq!(client.version(), CONTRACT_VERSION);
+
+    assert_eq!(
+        client.try_init(&BytesN::from_array(&e, &[2; 32])),
+        Err(Ok(Error::AlreadyInitialized.into()))
+    );
+}
+
+#[test]
+fn test_migrate() {
+    let e: Env = Default::default();
+    let contract_id = e.register_contract(None, DaoBridgeContract);
+    let client = DaoBridgeContractClient::new(&e, &contract_id);
+    let token_id = BytesN::from_array(&e, &[1; 32]);
+
+    // What a v0 bridge's init left in storage: no admin, no version
+    e.as_contract(&contract_id, || {
+        e.storage().set(DataKey::CanMint(token_id.clone()), true);
+    });
+    assert_eq!(client.version(), 0);
+
+    let admin = e.accounts().generate_and_create();
+    e.set_source_account(&admin);
+    assert_eq!(
+        client.try_init(&token_id),
+        Err(Ok(Error::AlreadyInitialized.into()))
+    );
+
+    client.migrate();
+    assert_eq!(client.version(), CONTRACT_VERSION);
+
+    // The v0 bridge is now owned by whoever migrated it
+    let other = e.accounts().generate_and_create();
+    e.set_source_account(&other);
+    assert_eq!(client.try_migrate(), Err(Ok(Error::NotAuthorized.into())));
+    assert_eq!(
+        client.try_init(&BytesN::from_array(&e, &[2; 32])),
+        Err(Ok(Error::AlreadyInitialized.into()))
+    );
+
+    // Already current, nothing to do
+    e.set_source_account(&admin);
+    client.migrate();
+    assert_eq!(client.version(), CONTRACT_VERSION);
+}
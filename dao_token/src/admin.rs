@@ -1,25 +1,114 @@
-use crate::storage_types::DataKey;
+use crate::error::Error;
+use crate::storage_types::{AdminNomination, DataKey, Role};
 use soroban_auth::{Identifier, Signature};
-use soroban_sdk::Env;
+use soroban_sdk::{panic_with_error, Env, Vec};
 
+pub const ALL_ROLES: [Role; 4] = [Role::Minter, Role::Pauser, Role::Compliance, Role::Admin];
+
+// Counts the v0 administrator too, so an unmigrated contract can't be
+// initialized again.
 pub fn has_administrator(e: &Env) -> bool {
+    e.storage().has(DataKey::Role(Role::Admin)) || e.storage().has(DataKey::Admin)
+}
+
+pub fn read_v0_administrator(e: &Env) -> Option<Identifier> {
     let key = DataKey::Admin;
-    e.storage().has(key)
+    e.storage().get(key).map(|admin| admin.unwrap())
 }
 
-fn read_administrator(e: &Env) -> Identifier {
+pub fn remove_v0_administrator(e: &Env) {
     let key = DataKey::Admin;
-    e.storage().get_unchecked(key).unwrap()
+    e.storage().remove(key);
+}
+
+pub fn read_role(e: &Env, role: Role) -> Vec<Identifier> {
+    let key = DataKey::Role(role);
+    e.storage()
+        .get(key)
+        .unwrap_or_else(|| Ok(Vec::new(e)))
+        .unwrap()
+}
+
+fn write_role(e: &Env, role: Role, members: Vec<Identifier>) {
+    let key = DataKey::Role(role);
+    e.storage().set(key, members);
+}
+
+pub fn has_role(e: &Env, role: Role, id: &Identifier) -> bool {
+    read_role(e, role).contains(id)
+}
+
+// Returns false if `id` already held the role.
+pub fn grant_role(e: &Env, role: Role, id: Identifier) -> bool {
+    let mut members = read_role(e, role);
+    if members.contains(&id) {
+        return false;
+    }
+    members.push_back(id);
+    write_role(e, role, members);
+    true
+}
+
+// Returns false if `id` did not hold the role.
+pub fn revoke_role(e: &Env, role: Role, id: &Identifier) -> bool {
+    let mut members = read_role(e, role);
+    match members.first_index_of(id) {
+        Some(i) => {
+            if role == Role::Admin && members.len() == 1 {
+                panic_with_error!(e, Error::LastAdmin)
+            }
+            members.remove_unchecked(i);
+            write_role(e, role, members);
+            true
+        }
+        None => false,
+    }
 }
 
 pub fn write_administrator(e: &Env, id: Identifier) {
-    let key = DataKey::Admin;
-    e.storage().set(key, id);
+    for role in ALL_ROLES {
+        grant_role(e, role, id.clone());
+    }
+}
+
+// Hands every role held by `from` over to `to`.
+pub fn transfer_roles(e: &Env, from: &Identifier, to: &Identifier) {
+    if from == to {
+        return;
+    }
+    for role in ALL_ROLES {
+        if has_role(e, role, from) {
+            grant_role(e, role, to.clone());
+            revoke_role(e, role, from);
+        }
+    }
+}
+
+pub fn read_nomination(e: &Env) -> Option<AdminNomination> {
+    let key = DataKey::Nominee;
+    e.storage().get(key).map(|nomination| nomination.unwrap())
+}
+
+pub fn write_nomination(e: &Env, nomination: AdminNomination) {
+    let key = DataKey::Nominee;
+    e.storage().set(key, nomination);
+}
+
+pub fn remove_nomination(e: &Env) {
+    let key = DataKey::Nominee;
+    e.storage().remove(key);
+}
+
+pub fn check_role(e: &Env, role: Role, auth: &Signature) {
+    let auth_id = auth.identifier(e);
+    if !has_role(e, role, &auth_id) {
+        panic_with_error!(e, Error::NotAuthorized)
+    }
 }
 
-pub fn check_admin(e: &Env, auth: &Signature) {
+pub fn check_any_role(e: &Env, roles: &[Role], auth: &Signature) {
     let auth_id = auth.identifier(e);
-    if auth_id != read_administrator(e) {
-        panic!("not authorized by admin")
+    if !roles.iter().any(|role| has_role(e, *role, &auth_id)) {
+        panic_with_error!(e, Error::NotAuthorized)
     }
 }
@@ -1,25 +1,33 @@
-use crate::storage_types::{AllowanceDataKey, DataKey};
+use crate::error::Error;
+use crate::storage_types::{AllowanceDataKey, AllowanceValue, DataKey};
+use crate::version::{read_v1_allowance, remove_v1_allowance};
 use soroban_auth::Identifier;
-use soroban_sdk::Env;
+use soroban_sdk::{panic_with_error, Env};
 
 pub fn read_allowance(e: &Env, from: Identifier, spender: Identifier) -> i128 {
-    let key = DataKey::Allowance(AllowanceDataKey { from, spender });
-    if let Some(allowance) = e.storage().get(key) {
-        allowance.unwrap()
+    let allowance_key = AllowanceDataKey { from, spender };
+    let key = DataKey::Allow(allowance_key.clone());
+    if let Some(allowance) = e.storage().get::<_, AllowanceValue>(key) {
+        allowance.unwrap().amount
     } else {
-        0
+        read_v1_allowance(e, &allowance_key).unwrap_or(0)
     }
 }
 
 pub fn write_allowance(e: &Env, from: Identifier, spender: Identifier, amount: i128) {
-    let key = DataKey::Allowance(AllowanceDataKey { from, spender });
-    e.storage().set(key, amount);
+    let allowance_key = AllowanceDataKey { from, spender };
+    remove_v1_allowance(e, &allowance_key);
+    let key = DataKey::Allow(allowance_key);
+    e.storage().set(key, AllowanceValue { amount });
 }
 
 pub fn spend_allowance(e: &Env, from: Identifier, spender: Identifier, amount: i128) {
     let allowance = read_allowance(e, from.clone(), spender.clone());
     if allowance < amount {
-        panic!("insufficient allowance");
+        panic_with_error!(e, Error::InsufficientAllowance);
     }
-    write_allowance(e, from, spender, allowance - amount);
+    let new_allowance = allowance
+        .checked_sub(amount)
+        .unwrap_or_else(|| panic_with_error!(e, Error::Overflow));
+    write_allowance(e, from, spender, new_allowance);
 }
@@ -1,6 +1,28 @@
+use crate::error::Error;
+use crate::holders::{add_holder, remove_holder};
 use crate::storage_types::DataKey;
 use soroban_auth::Identifier;
-use soroban_sdk::Env;
+use soroban_sdk::{panic_with_error, Env};
+
+// Accounts start out unauthorized and need set_auth before they can hold
+// tokens.
+pub const AUTH_REQUIRED: u32 = 1;
+// set_auth can take authorization away from an account again.
+pub const AUTH_REVOCABLE: u32 = 2;
+
+// Contracts initialized before auth flags existed behave as revocable.
+pub fn read_auth_flags(e: &Env) -> u32 {
+    let key = DataKey::AuthFlags;
+    e.storage().get(key).unwrap_or(Ok(AUTH_REVOCABLE)).unwrap()
+}
+
+pub fn write_auth_flags(e: &Env, flags: u32) {
+    if flags & !(AUTH_REQUIRED | AUTH_REVOCABLE) != 0 {
+        panic_with_error!(e, Error::InvalidFlags)
+    }
+    let key = DataKey::AuthFlags;
+    e.storage().set(key, flags);
+}
 
 pub fn read_balance(e: &Env, id: Identifier) -> i128 {
     let key = DataKey::Balance(id);
@@ -12,27 +34,38 @@ pub fn read_balance(e: &Env, id: Identifier) -> i128 {
 }
 
 fn write_balance(e: &Env, id: Identifier, amount: i128) {
-    let key = DataKey::Balance(id);
+    let key = DataKey::Balance(id.clone());
     e.storage().set(key, amount);
+    if amount == 0 {
+        remove_holder(e, id);
+    } else {
+        add_holder(e, id);
+    }
 }
 
 pub fn receive_balance(e: &Env, id: Identifier, amount: i128) {
     let balance = read_balance(e, id.clone());
     if !is_authorized(e, id.clone()) {
-        panic!("can't receive when deauthorized");
+        panic_with_error!(e, Error::ReceiveDeauthorized);
     }
-    write_balance(e, id, balance + amount);
+    let new_balance = balance
+        .checked_add(amount)
+        .unwrap_or_else(|| panic_with_error!(e, Error::Overflow));
+    write_balance(e, id, new_balance);
 }
 
 pub fn spend_balance(e: &Env, id: Identifier, amount: i128) {
     let balance = read_balance(e, id.clone());
     if !is_authorized(e, id.clone()) {
-        panic!("can't spend when deauthorized");
+        panic_with_error!(e, Error::SpendDeauthorized);
     }
     if balance < amount {
-        panic!("insufficient balance");
+        panic_with_error!(e, Error::InsufficientBalance);
     }
-    write_balance(e, id, balance - amount);
+    let new_balance = balance
+        .checked_sub(amount)
+        .unwrap_or_else(|| panic_with_error!(e, Error::Overflow));
+    write_balance(e, id, new_balance);
 }
 
 pub fn is_authorized(e: &Env, id: Identifier) -> bool {
@@ -40,11 +73,17 @@ pub fn is_authorized(e: &Env, id: Identifier) -> bool {
     if let Some(state) = e.storage().get(key) {
         state.unwrap()
     } else {
-        true
+        read_auth_flags(e) & AUTH_REQUIRED == 0
     }
 }
 
 pub fn write_authorization(e: &Env, id: Identifier, is_authorized: bool) {
+    if !is_authorized
+        && read_auth_flags(e) & AUTH_REVOCABLE == 0
+        && crate::balance::is_authorized(e, id.clone())
+    {
+        panic_with_error!(e, Error::NotRevocable)
+    }
     let key = DataKey::State(id);
     e.storage().set(key, is_authorized);
 }
@@ -1,18 +1,31 @@
-use crate::admin::{check_admin, has_administrator, write_administrator};
+use crate::admin::{
+    check_any_role, check_role, grant_role, has_administrator, has_role, read_nomination,
+    read_role, read_v0_administrator, remove_nomination, revoke_role, transfer_roles,
+    write_administrator, write_nomination,
+};
 use crate::allowance::{read_allowance, spend_allowance, write_allowance};
-use crate::balance::{is_authorized, write_authorization};
+use crate::balance::{is_authorized, read_auth_flags, write_auth_flags, write_authorization};
 use crate::balance::{read_balance, receive_balance, spend_balance};
+use crate::error::Error;
 use crate::event;
+use crate::fee::{charge_fee, is_exempt, read_fee_bps, read_treasury, write_exempt, write_fee};
+use crate::holders::{add_holder, read_holder_count, read_holders};
 use crate::metadata::{
-    read_decimal, read_name, read_symbol, write_decimal, write_name, write_symbol,
+    read_decimal, read_metadata, read_name, read_symbol, write_decimal, write_info, write_name,
+    write_symbol,
+};
+use crate::pause::{check_not_paused, is_paused, write_paused};
+use crate::storage_types::{AdminNomination, DataKey, Metadata, Role, TokenInfo, VestingGrant};
+use crate::version::{migrate, read_version, write_version, CONTRACT_VERSION};
+use crate::vesting::{
+    add_grant, read_grants, read_vesting, vested_amount, write_grants, write_vesting,
 };
-use crate::storage_types::DataKey;
 use soroban_auth::verify;
-use soroban_auth::{Identifier, Signature};
-use soroban_sdk::{contractimpl, symbol, Bytes, Env};
+use soroban_auth::{Ed25519Signature, Identifier, Signature};
+use soroban_sdk::{contractimpl, panic_with_error, symbol, Bytes, BytesN, Env, IntoVal, Vec};
 
 pub trait TokenTrait {
-    fn initialize(e: Env, admin: Identifier, decimal: u32, name: Bytes, symbol: Bytes);
+    fn initialize(e: Env, admin: Identifier, decimal: u32, name: Bytes, symbol: Bytes, flags: u32);
 
     fn nonce(e: Env, id: Identifier) -> i128;
 
@@ -22,6 +35,15 @@ pub trait TokenTrait {
 
     fn decr_allow(e: Env, from: Signature, nonce: i128, spender: Identifier, amount: i128);
 
+    fn permit(
+        e: Env,
+        owner: Identifier,
+        spender: Identifier,
+        amount: i128,
+        deadline: u32,
+        signature: BytesN<64>,
+    );
+
     fn balance(e: Env, id: Identifier) -> i128;
 
     fn spendable(e: Env, id: Identifier) -> i128;
@@ -39,6 +61,17 @@ pub trait TokenTrait {
         amount: i128,
     );
 
+    fn xfer_call(
+        e: Env,
+        from: Signature,
+        nonce: i128,
+        to_contract: BytesN<32>,
+        amount: i128,
+        data: Bytes,
+    );
+
+    fn xfer_memo(e: Env, from: Signature, nonce: i128, to: Identifier, amount: i128, memo: Bytes);
+
     fn burn(e: Env, from: Signature, nonce: i128, amount: i128);
 
     fn burn_from(e: Env, spender: Signature, nonce: i128, from: Identifier, amount: i128);
@@ -47,31 +80,102 @@ pub trait TokenTrait {
 
     fn set_auth(e: Env, admin: Signature, nonce: i128, id: Identifier, authorize: bool);
 
+    fn auth_batch(e: Env, admin: Signature, nonce: i128, ids: Vec<Identifier>);
+
+    fn auth_flags(e: Env) -> u32;
+
     fn mint(e: Env, admin: Signature, nonce: i128, to: Identifier, amount: i128);
 
     fn set_admin(e: Env, admin: Signature, nonce: i128, new_admin: Identifier);
 
+    fn accept_adm(e: Env, new_admin: Signature, nonce: i128);
+
+    fn cancel_adm(e: Env, admin: Signature, nonce: i128);
+
+    fn nominee(e: Env) -> Option<Identifier>;
+
     fn decimals(e: Env) -> u32;
 
     fn name(e: Env) -> Bytes;
 
     fn symbol(e: Env) -> Bytes;
+
+    fn set_info(e: Env, admin: Signature, nonce: i128, info: TokenInfo);
+
+    fn metadata(e: Env) -> Metadata;
+
+    #[allow(clippy::too_many_arguments)]
+    fn grant_vest(
+        e: Env,
+        admin: Signature,
+        nonce: i128,
+        beneficiary: Identifier,
+        total: i128,
+        start: u32,
+        cliff: u32,
+        duration: u32,
+    ) -> u32;
+
+    fn claim_vest(e: Env, beneficiary: Identifier) -> i128;
+
+    fn vesting_of(e: Env, grant_id: u32) -> Option<VestingGrant>;
+
+    fn grants_of(e: Env, id: Identifier) -> Vec<u32>;
+
+    fn add_role(e: Env, admin: Signature, nonce: i128, role: Role, id: Identifier);
+
+    fn del_role(e: Env, admin: Signature, nonce: i128, role: Role, id: Identifier);
+
+    fn has_role(e: Env, role: Role, id: Identifier) -> bool;
+
+    fn members(e: Env, role: Role) -> Vec<Identifier>;
+
+    fn pause(e: Env, admin: Signature, nonce: i128);
+
+    fn unpause(e: Env, admin: Signature, nonce: i128);
+
+    fn is_paused(e: Env) -> bool;
+
+    fn holders(e: Env, start: u32, limit: u32) -> Vec<Identifier>;
+
+    fn holder_cnt(e: Env) -> u32;
+
+    fn reindex(e: Env, admin: Signature, nonce: i128, ids: Vec<Identifier>);
+
+    fn set_fee(e: Env, admin: Signature, nonce: i128, fee_bps: u32, treasury: Identifier);
+
+    fn set_exempt(e: Env, admin: Signature, nonce: i128, id: Identifier, exempt: bool);
+
+    fn fee_bps(e: Env) -> u32;
+
+    fn treasury(e: Env) -> Option<Identifier>;
+
+    fn is_exempt(e: Env, id: Identifier) -> bool;
+
+    fn migrate(e: Env, admin: Signature, nonce: i128);
+
+    fn version(e: Env) -> u32;
 }
 
+const MAX_MEMO_LEN: u32 = 128;
+
 fn read_nonce(e: &Env, id: &Identifier) -> i128 {
     let key = DataKey::Nonce(id.clone());
     e.storage().get(key).unwrap_or(Ok(0)).unwrap()
 }
 
+fn check_nonnegative_amount(e: &Env, amount: i128) {
+    if amount < 0 {
+        panic_with_error!(e, Error::NegativeAmount)
+    }
+}
+
 fn verify_and_consume_nonce(e: &Env, auth: &Signature, expected_nonce: i128) {
-    match auth {
-        Signature::Invoker => {
-            if expected_nonce != 0 {
-                panic!("nonce should be zero for Invoker")
-            }
-            return;
+    if let Signature::Invoker = auth {
+        if expected_nonce != 0 {
+            panic_with_error!(e, Error::IncorrectNonce)
         }
-        _ => {}
+        return;
     }
 
     let id = auth.identifier(e);
@@ -79,7 +183,7 @@ fn verify_and_consume_nonce(e: &Env, auth: &Signature, expected_nonce: i128) {
     let nonce = read_nonce(e, &id);
 
     if nonce != expected_nonce {
-        panic!("incorrect nonce")
+        panic_with_error!(e, Error::IncorrectNonce)
     }
     e.storage().set(key, &nonce + 1);
 }
@@ -88,13 +192,18 @@ pub struct Token;
 
 #[contractimpl]
 impl TokenTrait for Token {
-    fn initialize(e: Env, admin: Identifier, decimal: u32, name: Bytes, symbol: Bytes) {
+    fn initialize(e: Env, admin: Identifier, decimal: u32, name: Bytes, symbol: Bytes, flags: u32) {
         if has_administrator(&e) {
-            panic!("already initialized")
+            panic_with_error!(&e, Error::AlreadyInitialized)
         }
         write_administrator(&e, admin);
+        write_auth_flags(&e, flags);
+        write_version(&e, CONTRACT_VERSION);
 
-        write_decimal(&e, u8::try_from(decimal).expect("Decimal must fit in a u8"));
+        write_decimal(
+            &e,
+            u8::try_from(decimal).unwrap_or_else(|_| panic_with_error!(&e, Error::DecimalTooLarge)),
+        );
         write_name(&e, name);
         write_symbol(&e, symbol);
     }
@@ -108,6 +217,8 @@ impl TokenTrait for Token {
     }
 
     fn incr_allow(e: Env, from: Signature, nonce: i128, spender: Identifier, amount: i128) {
+        check_nonnegative_amount(&e, amount);
+
         verify_and_consume_nonce(&e, &from, nonce);
 
         let from_id = from.identifier(&e);
@@ -122,13 +233,15 @@ impl TokenTrait for Token {
         let allowance = read_allowance(&e, from_id.clone(), spender.clone());
         let new_allowance = allowance
             .checked_add(amount)
-            .expect("Updated allowance doesn't fit in an i128");
+            .unwrap_or_else(|| panic_with_error!(&e, Error::Overflow));
 
         write_allowance(&e, from_id.clone(), spender.clone(), new_allowance);
         event::incr_allow(&e, from_id, spender, amount);
     }
 
     fn decr_allow(e: Env, from: Signature, nonce: i128, spender: Identifier, amount: i128) {
+        check_nonnegative_amount(&e, amount);
+
         verify_and_consume_nonce(&e, &from, nonce);
 
         let from_id = from.identifier(&e);
@@ -149,6 +262,43 @@ impl TokenTrait for Token {
         event::decr_allow(&e, from_id, spender, amount);
     }
 
+    fn permit(
+        e: Env,
+        owner: Identifier,
+        spender: Identifier,
+        amount: i128,
+        deadline: u32,
+        signature: BytesN<64>,
+    ) {
+        check_nonnegative_amount(&e, amount);
+
+        if e.ledger().sequence() > deadline {
+            panic_with_error!(&e, Error::PermitExpired)
+        }
+
+        // The signed payload carries the contract id and network passphrase,
+        // so a permit can't be replayed against another token or network.
+        let auth = match &owner {
+            Identifier::Ed25519(public_key) => Signature::Ed25519(Ed25519Signature {
+                public_key: public_key.clone(),
+                signature,
+            }),
+            _ => panic_with_error!(&e, Error::UnsupportedSigner),
+        };
+
+        let nonce = read_nonce(&e, &owner);
+        verify_and_consume_nonce(&e, &auth, nonce);
+
+        verify(
+            &e,
+            &auth,
+            symbol!("permit"),
+            (&owner, nonce, &spender, &amount, deadline),
+        );
+        write_allowance(&e, owner.clone(), spender.clone(), amount);
+        event::permit(&e, owner, spender, amount);
+    }
+
     fn balance(e: Env, id: Identifier) -> i128 {
         read_balance(&e, id)
     }
@@ -162,12 +312,16 @@ impl TokenTrait for Token {
     }
 
     fn xfer(e: Env, from: Signature, nonce: i128, to: Identifier, amount: i128) {
+        check_not_paused(&e);
+        check_nonnegative_amount(&e, amount);
+
         verify_and_consume_nonce(&e, &from, nonce);
 
         let from_id = from.identifier(&e);
 
         verify(&e, &from, symbol!("xfer"), (&from_id, nonce, &to, &amount));
         spend_balance(&e, from_id.clone(), amount);
+        let amount = charge_fee(&e, &from_id, &to, amount);
         receive_balance(&e, to.clone(), amount);
         event::transfer(&e, from_id, to, amount);
     }
@@ -180,6 +334,9 @@ impl TokenTrait for Token {
         to: Identifier,
         amount: i128,
     ) {
+        check_not_paused(&e);
+        check_nonnegative_amount(&e, amount);
+
         verify_and_consume_nonce(&e, &spender, nonce);
 
         let spender_id = spender.identifier(&e);
@@ -192,11 +349,75 @@ impl TokenTrait for Token {
         );
         spend_allowance(&e, from.clone(), spender_id, amount);
         spend_balance(&e, from.clone(), amount);
+        let amount = charge_fee(&e, &from, &to, amount);
         receive_balance(&e, to.clone(), amount);
         event::transfer(&e, from, to, amount)
     }
 
+    fn xfer_call(
+        e: Env,
+        from: Signature,
+        nonce: i128,
+        to_contract: BytesN<32>,
+        amount: i128,
+        data: Bytes,
+    ) {
+        check_not_paused(&e);
+        check_nonnegative_amount(&e, amount);
+
+        verify_and_consume_nonce(&e, &from, nonce);
+
+        let from_id = from.identifier(&e);
+        let to = Identifier::Contract(to_contract.clone());
+
+        verify(
+            &e,
+            &from,
+            symbol!("xfer_call"),
+            (&from_id, nonce, &to_contract, &amount, &data),
+        );
+        spend_balance(&e, from_id.clone(), amount);
+        let amount = charge_fee(&e, &from_id, &to, amount);
+        receive_balance(&e, to.clone(), amount);
+        event::transfer(&e, from_id.clone(), to, amount);
+
+        // A failing callback aborts the invocation, which rolls back the
+        // transfer above.
+        e.invoke_contract::<()>(
+            &to_contract,
+            &symbol!("on_receive"),
+            (from_id, amount, data).into_val(&e),
+        );
+    }
+
+    fn xfer_memo(e: Env, from: Signature, nonce: i128, to: Identifier, amount: i128, memo: Bytes) {
+        check_not_paused(&e);
+        check_nonnegative_amount(&e, amount);
+
+        if memo.len() > MAX_MEMO_LEN {
+            panic_with_error!(&e, Error::MemoTooLong)
+        }
+
+        verify_and_consume_nonce(&e, &from, nonce);
+
+        let from_id = from.identifier(&e);
+
+        verify(
+            &e,
+            &from,
+            symbol!("xfer_memo"),
+            (&from_id, nonce, &to, &amount, &memo),
+        );
+        spend_balance(&e, from_id.clone(), amount);
+        let amount = charge_fee(&e, &from_id, &to, amount);
+        receive_balance(&e, to.clone(), amount);
+        event::transfer_memo(&e, from_id, to, amount, memo);
+    }
+
     fn burn(e: Env, from: Signature, nonce: i128, amount: i128) {
+        check_not_paused(&e);
+        check_nonnegative_amount(&e, amount);
+
         verify_and_consume_nonce(&e, &from, nonce);
 
         let from_id = from.identifier(&e);
@@ -207,6 +428,9 @@ impl TokenTrait for Token {
     }
 
     fn burn_from(e: Env, spender: Signature, nonce: i128, from: Identifier, amount: i128) {
+        check_not_paused(&e);
+        check_nonnegative_amount(&e, amount);
+
         verify_and_consume_nonce(&e, &spender, nonce);
 
         let spender_id = spender.identifier(&e);
@@ -223,7 +447,9 @@ impl TokenTrait for Token {
     }
 
     fn clawback(e: Env, admin: Signature, nonce: i128, from: Identifier, amount: i128) {
-        check_admin(&e, &admin);
+        check_nonnegative_amount(&e, amount);
+
+        check_role(&e, Role::Compliance, &admin);
         verify_and_consume_nonce(&e, &admin, nonce);
 
         let admin_id = admin.identifier(&e);
@@ -239,7 +465,7 @@ impl TokenTrait for Token {
     }
 
     fn set_auth(e: Env, admin: Signature, nonce: i128, id: Identifier, authorize: bool) {
-        check_admin(&e, &admin);
+        check_role(&e, Role::Compliance, &admin);
 
         verify_and_consume_nonce(&e, &admin, nonce);
 
@@ -255,8 +481,30 @@ impl TokenTrait for Token {
         event::set_auth(&e, admin_id, id, authorize);
     }
 
+    fn auth_batch(e: Env, admin: Signature, nonce: i128, ids: Vec<Identifier>) {
+        check_role(&e, Role::Compliance, &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(&e, &admin, symbol!("auth_batch"), (&admin_id, nonce, &ids));
+        for id in ids.iter() {
+            let id = id.unwrap();
+            write_authorization(&e, id.clone(), true);
+            event::set_auth(&e, admin_id.clone(), id, true);
+        }
+    }
+
+    fn auth_flags(e: Env) -> u32 {
+        read_auth_flags(&e)
+    }
+
     fn mint(e: Env, admin: Signature, nonce: i128, to: Identifier, amount: i128) {
-        check_admin(&e, &admin);
+        check_not_paused(&e);
+        check_nonnegative_amount(&e, amount);
+
+        check_role(&e, Role::Minter, &admin);
 
         verify_and_consume_nonce(&e, &admin, nonce);
 
@@ -273,7 +521,7 @@ impl TokenTrait for Token {
     }
 
     fn set_admin(e: Env, admin: Signature, nonce: i128, new_admin: Identifier) {
-        check_admin(&e, &admin);
+        check_role(&e, Role::Admin, &admin);
 
         verify_and_consume_nonce(&e, &admin, nonce);
 
@@ -285,8 +533,54 @@ impl TokenTrait for Token {
             symbol!("set_admin"),
             (&admin_id, nonce, &new_admin),
         );
-        write_administrator(&e, new_admin.clone());
-        event::set_admin(&e, admin_id, new_admin);
+        let nomination = AdminNomination {
+            admin: admin_id.clone(),
+            nominee: new_admin.clone(),
+        };
+        write_nomination(&e, nomination);
+        event::nominate_admin(&e, admin_id, new_admin);
+    }
+
+    fn accept_adm(e: Env, new_admin: Signature, nonce: i128) {
+        let nomination =
+            read_nomination(&e).unwrap_or_else(|| panic_with_error!(&e, Error::NoNomination));
+        let new_admin_id = new_admin.identifier(&e);
+        if new_admin_id != nomination.nominee {
+            panic_with_error!(&e, Error::NotNominee)
+        }
+        if !has_role(&e, Role::Admin, &nomination.admin) {
+            panic_with_error!(&e, Error::NominatorRemoved)
+        }
+
+        verify_and_consume_nonce(&e, &new_admin, nonce);
+
+        verify(
+            &e,
+            &new_admin,
+            symbol!("accept_adm"),
+            (&new_admin_id, nonce),
+        );
+        remove_nomination(&e);
+        transfer_roles(&e, &nomination.admin, &new_admin_id);
+        event::set_admin(&e, nomination.admin, new_admin_id);
+    }
+
+    fn cancel_adm(e: Env, admin: Signature, nonce: i128) {
+        check_role(&e, Role::Admin, &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(&e, &admin, symbol!("cancel_adm"), (&admin_id, nonce));
+        let nomination =
+            read_nomination(&e).unwrap_or_else(|| panic_with_error!(&e, Error::NoNomination));
+        remove_nomination(&e);
+        event::cancel_admin(&e, admin_id, nomination.nominee);
+    }
+
+    fn nominee(e: Env) -> Option<Identifier> {
+        read_nomination(&e).map(|nomination| nomination.nominee)
     }
 
     fn decimals(e: Env) -> u32 {
@@ -300,4 +594,285 @@ impl TokenTrait for Token {
     fn symbol(e: Env) -> Bytes {
         read_symbol(&e)
     }
+
+    fn set_info(e: Env, admin: Signature, nonce: i128, info: TokenInfo) {
+        check_role(&e, Role::Admin, &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(&e, &admin, symbol!("set_info"), (&admin_id, nonce, &info));
+        write_info(&e, info.clone());
+        event::set_info(&e, admin_id, info);
+    }
+
+    fn metadata(e: Env) -> Metadata {
+        read_metadata(&e)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn grant_vest(
+        e: Env,
+        admin: Signature,
+        nonce: i128,
+        beneficiary: Identifier,
+        total: i128,
+        start: u32,
+        cliff: u32,
+        duration: u32,
+    ) -> u32 {
+        check_role(&e, Role::Minter, &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(
+            &e,
+            &admin,
+            symbol!("grant_vest"),
+            (
+                &admin_id,
+                nonce,
+                &beneficiary,
+                &total,
+                start,
+                cliff,
+                duration,
+            ),
+        );
+        if total <= 0 {
+            panic_with_error!(&e, Error::InvalidVesting)
+        }
+        if duration == 0 || cliff > duration {
+            panic_with_error!(&e, Error::InvalidVesting)
+        }
+
+        let grant = VestingGrant {
+            recipient: beneficiary.clone(),
+            total,
+            claimed: 0,
+            start,
+            cliff,
+            duration,
+        };
+        let grant_id = add_grant(&e, grant.clone());
+        event::vest_grant(&e, admin_id, beneficiary, grant_id, grant);
+        grant_id
+    }
+
+    // Releases what has vested across all of the beneficiary's grants.
+    fn claim_vest(e: Env, beneficiary: Identifier) -> i128 {
+        check_not_paused(&e);
+
+        let grants = read_grants(&e, beneficiary.clone());
+        if grants.is_empty() {
+            panic_with_error!(&e, Error::NoVesting)
+        }
+
+        let mut amount: i128 = 0;
+        // fully claimed grants drop off the list, so it only ever holds
+        // grants that still have something to give
+        let mut remaining = Vec::new(&e);
+        for grant_id in grants.iter() {
+            let grant_id = grant_id.unwrap();
+            let mut grant = read_vesting(&e, grant_id).unwrap();
+            let claimable = vested_amount(&e, &grant) - grant.claimed;
+            if grant.claimed + claimable < grant.total {
+                remaining.push_back(grant_id);
+            }
+            if claimable <= 0 {
+                continue;
+            }
+            grant.claimed += claimable;
+            write_vesting(&e, grant_id, grant);
+            amount = amount
+                .checked_add(claimable)
+                .unwrap_or_else(|| panic_with_error!(&e, Error::Overflow));
+        }
+        if amount == 0 {
+            panic_with_error!(&e, Error::NothingToClaim)
+        }
+        if remaining.len() != grants.len() {
+            write_grants(&e, beneficiary.clone(), remaining);
+        }
+
+        receive_balance(&e, beneficiary.clone(), amount);
+        event::vest_claim(&e, beneficiary, amount);
+        amount
+    }
+
+    fn vesting_of(e: Env, grant_id: u32) -> Option<VestingGrant> {
+        read_vesting(&e, grant_id)
+    }
+
+    fn grants_of(e: Env, id: Identifier) -> Vec<u32> {
+        read_grants(&e, id)
+    }
+
+    fn add_role(e: Env, admin: Signature, nonce: i128, role: Role, id: Identifier) {
+        check_role(&e, Role::Admin, &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(
+            &e,
+            &admin,
+            symbol!("add_role"),
+            (&admin_id, nonce, role, &id),
+        );
+        if grant_role(&e, role, id.clone()) {
+            event::add_role(&e, admin_id, id, role);
+        }
+    }
+
+    fn del_role(e: Env, admin: Signature, nonce: i128, role: Role, id: Identifier) {
+        check_role(&e, Role::Admin, &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(
+            &e,
+            &admin,
+            symbol!("del_role"),
+            (&admin_id, nonce, role, &id),
+        );
+        if revoke_role(&e, role, &id) {
+            event::del_role(&e, admin_id, id, role);
+        }
+    }
+
+    fn has_role(e: Env, role: Role, id: Identifier) -> bool {
+        has_role(&e, role, &id)
+    }
+
+    fn members(e: Env, role: Role) -> Vec<Identifier> {
+        read_role(&e, role)
+    }
+
+    fn pause(e: Env, admin: Signature, nonce: i128) {
+        check_any_role(&e, &[Role::Pauser, Role::Admin], &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(&e, &admin, symbol!("pause"), (&admin_id, nonce));
+        write_paused(&e, true);
+        event::pause(&e, admin_id);
+    }
+
+    fn unpause(e: Env, admin: Signature, nonce: i128) {
+        check_any_role(&e, &[Role::Pauser, Role::Admin], &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(&e, &admin, symbol!("unpause"), (&admin_id, nonce));
+        write_paused(&e, false);
+        event::unpause(&e, admin_id);
+    }
+
+    fn is_paused(e: Env) -> bool {
+        is_paused(&e)
+    }
+
+    fn holders(e: Env, start: u32, limit: u32) -> Vec<Identifier> {
+        read_holders(&e, start, limit)
+    }
+
+    fn holder_cnt(e: Env) -> u32 {
+        read_holder_count(&e)
+    }
+
+    fn reindex(e: Env, admin: Signature, nonce: i128, ids: Vec<Identifier>) {
+        check_role(&e, Role::Admin, &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(&e, &admin, symbol!("reindex"), (&admin_id, nonce, &ids));
+        for id in ids.iter() {
+            let id = id.unwrap();
+            if read_balance(&e, id.clone()) > 0 {
+                add_holder(&e, id);
+            }
+        }
+    }
+
+    fn set_fee(e: Env, admin: Signature, nonce: i128, fee_bps: u32, treasury: Identifier) {
+        check_role(&e, Role::Admin, &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(
+            &e,
+            &admin,
+            symbol!("set_fee"),
+            (&admin_id, nonce, fee_bps, &treasury),
+        );
+        write_fee(&e, fee_bps, treasury.clone());
+        event::set_fee(&e, admin_id, treasury, fee_bps);
+    }
+
+    fn set_exempt(e: Env, admin: Signature, nonce: i128, id: Identifier, exempt: bool) {
+        check_role(&e, Role::Admin, &admin);
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(
+            &e,
+            &admin,
+            symbol!("set_exempt"),
+            (&admin_id, nonce, &id, exempt),
+        );
+        write_exempt(&e, id.clone(), exempt);
+        event::set_exempt(&e, admin_id, id, exempt);
+    }
+
+    fn fee_bps(e: Env) -> u32 {
+        read_fee_bps(&e)
+    }
+
+    fn treasury(e: Env) -> Option<Identifier> {
+        read_treasury(&e)
+    }
+
+    fn is_exempt(e: Env, id: Identifier) -> bool {
+        is_exempt(&e, id)
+    }
+
+    fn migrate(e: Env, admin: Signature, nonce: i128) {
+        // a v0 contract's administrator isn't in the role sets until this runs
+        match read_v0_administrator(&e) {
+            Some(v0_admin) if admin.identifier(&e) != v0_admin => {
+                panic_with_error!(&e, Error::NotAuthorized)
+            }
+            Some(_) => {}
+            None => check_role(&e, Role::Admin, &admin),
+        }
+
+        verify_and_consume_nonce(&e, &admin, nonce);
+
+        let admin_id = admin.identifier(&e);
+
+        verify(&e, &admin, symbol!("migrate"), (&admin_id, nonce));
+        let from = migrate(&e);
+        event::migrate(&e, admin_id, from, CONTRACT_VERSION);
+    }
+
+    fn version(e: Env) -> u32 {
+        read_version(&e)
+    }
 }
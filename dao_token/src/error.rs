@@ -0,0 +1,35 @@
+use soroban_sdk::contracterror;
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    AlreadyInitialized = 1,
+    DecimalTooLarge = 2,
+    NotAuthorized = 3,
+    IncorrectNonce = 4,
+    InsufficientBalance = 5,
+    InsufficientAllowance = 6,
+    ReceiveDeauthorized = 7,
+    SpendDeauthorized = 8,
+    Overflow = 9,
+    Paused = 10,
+    LastAdmin = 11,
+    NoNomination = 12,
+    NotNominee = 13,
+    NominatorRemoved = 14,
+    PermitExpired = 15,
+    UnsupportedSigner = 16,
+    MemoTooLong = 17,
+    InvalidVesting = 18,
+    // no longer raised, a beneficiary can hold several grants. Kept so the
+    // codes after it don't move.
+    AlreadyVesting = 19,
+    NoVesting = 20,
+    NothingToClaim = 21,
+    NegativeAmount = 22,
+    InvalidFee = 23,
+    InvalidFlags = 24,
+    NotRevocable = 25,
+    InfoTooLong = 26,
+}
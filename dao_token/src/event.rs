@@ -1,5 +1,6 @@
+use crate::storage_types::{Role, TokenInfo, VestingGrant};
 use soroban_auth::Identifier;
-use soroban_sdk::{symbol, Env};
+use soroban_sdk::{symbol, Bytes, Env};
 
 pub(crate) fn incr_allow(e: &Env, from: Identifier, to: Identifier, amount: i128) {
     let topics = (symbol!("incr_allow"), from, to);
@@ -11,11 +12,26 @@ pub(crate) fn decr_allow(e: &Env, from: Identifier, to: Identifier, amount: i128
     e.events().publish(topics, amount);
 }
 
+pub(crate) fn permit(e: &Env, from: Identifier, to: Identifier, amount: i128) {
+    let topics = (symbol!("permit"), from, to);
+    e.events().publish(topics, amount);
+}
+
 pub(crate) fn transfer(e: &Env, from: Identifier, to: Identifier, amount: i128) {
     let topics = (symbol!("transfer"), from, to);
     e.events().publish(topics, amount);
 }
 
+pub(crate) fn transfer_memo(e: &Env, from: Identifier, to: Identifier, amount: i128, memo: Bytes) {
+    let topics = (symbol!("transfer"), from, to);
+    e.events().publish(topics, (amount, memo));
+}
+
+pub(crate) fn fee(e: &Env, from: Identifier, treasury: Identifier, amount: i128) {
+    let topics = (symbol!("fee"), from, treasury);
+    e.events().publish(topics, amount);
+}
+
 pub(crate) fn mint(e: &Env, admin: Identifier, to: Identifier, amount: i128) {
     let topics = (symbol!("mint"), admin, to);
     e.events().publish(topics, amount);
@@ -36,7 +52,73 @@ pub(crate) fn set_admin(e: &Env, admin: Identifier, new_admin: Identifier) {
     e.events().publish(topics, new_admin);
 }
 
+pub(crate) fn nominate_admin(e: &Env, admin: Identifier, nominee: Identifier) {
+    let topics = (symbol!("nom_admin"), admin);
+    e.events().publish(topics, nominee);
+}
+
+pub(crate) fn cancel_admin(e: &Env, admin: Identifier, nominee: Identifier) {
+    let topics = (symbol!("cncl_admin"), admin);
+    e.events().publish(topics, nominee);
+}
+
 pub(crate) fn burn(e: &Env, from: Identifier, amount: i128) {
     let topics = (symbol!("burn"), from);
     e.events().publish(topics, amount);
 }
+
+pub(crate) fn vest_grant(
+    e: &Env,
+    admin: Identifier,
+    beneficiary: Identifier,
+    grant_id: u32,
+    grant: VestingGrant,
+) {
+    let topics = (symbol!("vest_grant"), admin, beneficiary);
+    e.events().publish(topics, (grant_id, grant));
+}
+
+pub(crate) fn vest_claim(e: &Env, beneficiary: Identifier, amount: i128) {
+    let topics = (symbol!("vest_claim"), beneficiary);
+    e.events().publish(topics, amount);
+}
+
+pub(crate) fn add_role(e: &Env, admin: Identifier, id: Identifier, role: Role) {
+    let topics = (symbol!("add_role"), admin, id);
+    e.events().publish(topics, role);
+}
+
+pub(crate) fn del_role(e: &Env, admin: Identifier, id: Identifier, role: Role) {
+    let topics = (symbol!("del_role"), admin, id);
+    e.events().publish(topics, role);
+}
+
+pub(crate) fn pause(e: &Env, admin: Identifier) {
+    let topics = (symbol!("pause"), admin);
+    e.events().publish(topics, ());
+}
+
+pub(crate) fn unpause(e: &Env, admin: Identifier) {
+    let topics = (symbol!("unpause"), admin);
+    e.events().publish(topics, ());
+}
+
+pub(crate) fn set_fee(e: &Env, admin: Identifier, treasury: Identifier, fee_bps: u32) {
+    let topics = (symbol!("set_fee"), admin, treasury);
+    e.events().publish(topics, fee_bps);
+}
+
+pub(crate) fn set_exempt(e: &Env, admin: Identifier, id: Identifier, exempt: bool) {
+    let topics = (symbol!("set_exempt"), admin, id);
+    e.events().publish(topics, exempt);
+}
+
+pub(crate) fn set_info(e: &Env, admin: Identifier, info: TokenInfo) {
+    let topics = (symbol!("set_info"), admin);
+    e.events().publish(topics, info);
+}
+
+pub(crate) fn migrate(e: &Env, admin: Identifier, from: u32, to: u32) {
+    let topics = (symbol!("migrate"), admin);
+    e.events().publish(topics, (from, to));
+}
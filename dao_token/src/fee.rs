@@ -0,0 +1,70 @@
+use crate::balance::receive_balance;
+use crate::error::Error;
+use crate::event;
+use crate::storage_types::DataKey;
+use soroban_auth::Identifier;
+use soroban_sdk::{panic_with_error, Env};
+
+// Fees are in basis points of the transferred amount.
+const BPS: u32 = 10_000;
+
+// Highest fee `set_fee` accepts, 10%, so an admin can't take most of every
+// transfer.
+pub const MAX_FEE_BPS: u32 = 1_000;
+
+pub fn read_fee_bps(e: &Env) -> u32 {
+    let key = DataKey::FeeBps;
+    e.storage().get(key).unwrap_or(Ok(0)).unwrap()
+}
+
+pub fn read_treasury(e: &Env) -> Option<Identifier> {
+    let key = DataKey::Treasury;
+    e.storage().get(key).map(|treasury| treasury.unwrap())
+}
+
+pub fn write_fee(e: &Env, fee_bps: u32, treasury: Identifier) {
+    if fee_bps > MAX_FEE_BPS {
+        panic_with_error!(e, Error::InvalidFee)
+    }
+    e.storage().set(DataKey::FeeBps, fee_bps);
+    e.storage().set(DataKey::Treasury, treasury);
+}
+
+pub fn is_exempt(e: &Env, id: Identifier) -> bool {
+    let key = DataKey::FeeExempt(id);
+    e.storage().get(key).unwrap_or(Ok(false)).unwrap()
+}
+
+pub fn write_exempt(e: &Env, id: Identifier, exempt: bool) {
+    let key = DataKey::FeeExempt(id);
+    e.storage().set(key, exempt);
+}
+
+// Moves the transfer fee for `amount` from an already spent transfer to the
+// treasury and returns what is left for the recipient. Transfers from or to
+// the treasury or an exempt identifier are free.
+pub fn charge_fee(e: &Env, from: &Identifier, to: &Identifier, amount: i128) -> i128 {
+    let treasury = match read_treasury(e) {
+        Some(treasury) => treasury,
+        None => return amount,
+    };
+    if *from == treasury
+        || *to == treasury
+        || is_exempt(e, from.clone())
+        || is_exempt(e, to.clone())
+    {
+        return amount;
+    }
+
+    let fee = amount
+        .checked_mul(i128::from(read_fee_bps(e)))
+        .unwrap_or_else(|| panic_with_error!(e, Error::Overflow))
+        / i128::from(BPS);
+    if fee == 0 {
+        return amount;
+    }
+
+    receive_balance(e, treasury.clone(), fee);
+    event::fee(e, from.clone(), treasury, fee);
+    amount - fee
+}
@@ -0,0 +1,70 @@
+use crate::storage_types::DataKey;
+use soroban_auth::Identifier;
+use soroban_sdk::{Env, Vec};
+
+// Holders are kept in a dense index so they can be paged through. Removal
+// swaps the last holder into the freed slot, so the order only stays stable
+// while no balance moves to or from zero.
+//
+// Balances written before the index existed aren't in it, and storage can't
+// be enumerated to find them. They are listed from their next balance change,
+// or once an admin passes them to `reindex`.
+
+// Most holders a single `holders` call returns, so a page stays well within
+// the invocation budget however large a limit is asked for.
+pub const MAX_HOLDERS_PAGE: u32 = 100;
+
+pub fn read_holder_count(e: &Env) -> u32 {
+    let key = DataKey::HolderCnt;
+    e.storage().get(key).unwrap_or(Ok(0)).unwrap()
+}
+
+fn write_holder_count(e: &Env, count: u32) {
+    let key = DataKey::HolderCnt;
+    e.storage().set(key, count);
+}
+
+fn read_holder(e: &Env, index: u32) -> Identifier {
+    let key = DataKey::Holder(index);
+    e.storage().get_unchecked(key).unwrap()
+}
+
+fn write_holder(e: &Env, index: u32, id: Identifier) {
+    e.storage().set(DataKey::Holder(index), id.clone());
+    e.storage().set(DataKey::HolderIdx(id), index);
+}
+
+pub fn add_holder(e: &Env, id: Identifier) {
+    if e.storage().has(DataKey::HolderIdx(id.clone())) {
+        return;
+    }
+    let count = read_holder_count(e);
+    write_holder(e, count, id);
+    write_holder_count(e, count + 1);
+}
+
+pub fn remove_holder(e: &Env, id: Identifier) {
+    let key = DataKey::HolderIdx(id);
+    let index: u32 = match e.storage().get(key.clone()) {
+        Some(index) => index.unwrap(),
+        None => return,
+    };
+    e.storage().remove(key);
+
+    let last = read_holder_count(e) - 1;
+    if index != last {
+        write_holder(e, index, read_holder(e, last));
+    }
+    e.storage().remove(DataKey::Holder(last));
+    write_holder_count(e, last);
+}
+
+pub fn read_holders(e: &Env, start: u32, limit: u32) -> Vec<Identifier> {
+    let limit = limit.min(MAX_HOLDERS_PAGE);
+    let end = start.saturating_add(limit).min(read_holder_count(e));
+    let mut holders = Vec::new(e);
+    for index in start..end {
+        holders.push_back(read_holder(e, index));
+    }
+    holders
+}
@@ -4,10 +4,22 @@ mod admin;
 mod allowance;
 mod balance;
 mod contract;
+mod error;
 mod event;
+mod fee;
+mod holders;
 mod metadata;
+mod pause;
 mod storage_types;
 mod test;
 pub mod testutils;
+mod version;
+mod vesting;
 
+pub use crate::balance::{AUTH_REQUIRED, AUTH_REVOCABLE};
 pub use crate::contract::TokenClient;
+pub use crate::error::Error;
+pub use crate::fee::MAX_FEE_BPS;
+pub use crate::holders::MAX_HOLDERS_PAGE;
+pub use crate::storage_types::{Metadata, TokenInfo};
+pub use crate::version::CONTRACT_VERSION;
@@ -1,5 +1,8 @@
-use crate::storage_types::DataKey;
-use soroban_sdk::{Bytes, Env};
+use crate::error::Error;
+use crate::storage_types::{DataKey, Metadata, TokenInfo};
+use soroban_sdk::{panic_with_error, Bytes, Env};
+
+const MAX_INFO_LEN: u32 = 256;
 
 pub fn read_decimal(e: &Env) -> u32 {
     let key = DataKey::Decimals;
@@ -30,3 +33,40 @@ pub fn write_symbol(e: &Env, d: Bytes) {
     let key = DataKey::Symbol;
     e.storage().set(key, d)
 }
+
+pub fn read_info(e: &Env) -> TokenInfo {
+    let key = DataKey::Info;
+    e.storage()
+        .get(key)
+        .unwrap_or_else(|| {
+            Ok(TokenInfo {
+                desc: Bytes::new(e),
+                domain: Bytes::new(e),
+                icon_uri: Bytes::new(e),
+                remote_id: Bytes::new(e),
+            })
+        })
+        .unwrap()
+}
+
+pub fn write_info(e: &Env, info: TokenInfo) {
+    let fields = [&info.desc, &info.domain, &info.icon_uri, &info.remote_id];
+    if fields.iter().any(|field| field.len() > MAX_INFO_LEN) {
+        panic_with_error!(e, Error::InfoTooLong)
+    }
+    let key = DataKey::Info;
+    e.storage().set(key, info)
+}
+
+pub fn read_metadata(e: &Env) -> Metadata {
+    let info = read_info(e);
+    Metadata {
+        decimals: read_decimal(e),
+        name: read_name(e),
+        symbol: read_symbol(e),
+        desc: info.desc,
+        domain: info.domain,
+        icon_uri: info.icon_uri,
+        remote_id: info.remote_id,
+    }
+}
@@ -0,0 +1,19 @@
+use crate::error::Error;
+use crate::storage_types::DataKey;
+use soroban_sdk::{panic_with_error, Env};
+
+pub fn is_paused(e: &Env) -> bool {
+    let key = DataKey::Paused;
+    e.storage().get(key).unwrap_or(Ok(false)).unwrap()
+}
+
+pub fn write_paused(e: &Env, paused: bool) {
+    let key = DataKey::Paused;
+    e.storage().set(key, paused);
+}
+
+pub fn check_not_paused(e: &Env) {
+    if is_paused(e) {
+        panic_with_error!(e, Error::Paused)
+    }
+}
@@ -1,5 +1,5 @@
 use soroban_auth::Identifier;
-use soroban_sdk::contracttype;
+use soroban_sdk::{contracttype, Bytes};
 
 #[derive(Clone)]
 #[contracttype]
@@ -8,15 +8,88 @@ pub struct AllowanceDataKey {
     pub spender: Identifier,
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AllowanceValue {
+    pub amount: i128,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct VestingGrant {
+    pub recipient: Identifier,
+    pub total: i128,
+    pub claimed: i128,
+    pub start: u32,
+    pub cliff: u32,
+    pub duration: u32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Role {
+    Minter,
+    Pauser,
+    Compliance,
+    Admin,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct AdminNomination {
+    pub admin: Identifier,
+    pub nominee: Identifier,
+}
+
+// Descriptive fields the admin can update after initialize.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct TokenInfo {
+    pub desc: Bytes,
+    pub domain: Bytes,
+    pub icon_uri: Bytes,
+    pub remote_id: Bytes,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Metadata {
+    pub decimals: u32,
+    pub name: Bytes,
+    pub symbol: Bytes,
+    pub desc: Bytes,
+    pub domain: Bytes,
+    pub icon_uri: Bytes,
+    pub remote_id: Bytes,
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
+    // Schema v0/v1 allowance, a bare i128. Only read to migrate it to Allow.
     Allowance(AllowanceDataKey),
     Balance(Identifier),
     Nonce(Identifier),
     State(Identifier),
+    Role(Role),
+    // Schema v0 administrator. Only read to migrate it into the role sets.
     Admin,
     Decimals,
     Name,
     Symbol,
+    Vesting(u32),
+    Paused,
+    Nominee,
+    Holder(u32),
+    HolderIdx(Identifier),
+    HolderCnt,
+    FeeBps,
+    Treasury,
+    FeeExempt(Identifier),
+    AuthFlags,
+    Info,
+    Version,
+    Allow(AllowanceDataKey),
+    VestCnt,
+    Grants(Identifier),
 }
@@ -1,16 +1,50 @@
 #![cfg(test)]
+extern crate std;
 
+use crate::storage_types::{AllowanceDataKey, AllowanceValue, DataKey, Role};
 use crate::testutils::{register_test_contract as register_token, to_ed25519, Token};
-use crate::TokenClient;
+use crate::{
+    Error, TokenClient, TokenInfo, AUTH_REQUIRED, AUTH_REVOCABLE, CONTRACT_VERSION, MAX_FEE_BPS,
+    MAX_HOLDERS_PAGE,
+};
 use ed25519_dalek::Keypair;
-use rand::{thread_rng, RngCore};
-use soroban_auth::{Ed25519Signature, Signature};
-use soroban_sdk::{Env, IntoVal};
+use rand::{thread_rng, Rng, RngCore};
+use soroban_auth::{Ed25519Signature, Identifier, Signature, SignaturePayload, SignaturePayloadV0};
+use soroban_sdk::testutils::ed25519::Sign;
+use soroban_sdk::testutils::{Accounts, Events, Ledger};
+use soroban_sdk::{contractimpl, symbol, vec, Bytes, BytesN, Env, IntoVal, RawVal, TryIntoVal};
+
+pub struct Receiver;
+
+#[contractimpl]
+impl Receiver {
+    pub fn on_receive(e: Env, from: Identifier, amount: i128, data: Bytes) {
+        if data == Bytes::from_slice(&e, b"reject") {
+            panic!("rejected")
+        }
+        e.storage().set(symbol!("from"), from);
+        e.storage().set(symbol!("amount"), amount);
+        e.storage().set(symbol!("data"), data);
+    }
+}
 
 fn generate_keypair() -> Keypair {
     Keypair::generate(&mut thread_rng())
 }
 
+// Makes `account` the invoker, so calls can authenticate with
+// `Signature::Invoker` and a zero nonce.
+fn invoke_as(e: &Env, account: &Identifier) {
+    match account {
+        Identifier::Account(id) => e.set_source_account(id),
+        _ => panic!("not an account"),
+    }
+}
+
+fn generate_account(e: &Env) -> Identifier {
+    Identifier::Account(e.accounts().generate_and_create())
+}
+
 #[test]
 fn test() {
     let e: Env = Default::default();
@@ -55,18 +89,23 @@ fn test() {
 
     token.set_admin(&admin1, &admin2_id);
     assert_eq!(token.nonce(&admin1_id), 2);
+    assert_eq!(token.nominee(), Some(admin2_id.clone()));
 
-    token.set_auth(&admin2, &user2_id, false);
-    assert_eq!(token.authorized(&user2_id), false);
+    token.accept_adm(&admin2);
+    assert_eq!(token.nominee(), None);
     assert_eq!(token.nonce(&admin2_id), 1);
 
-    token.set_auth(&admin2, &user3_id, true);
-    assert_eq!(token.authorized(&user3_id), true);
+    token.set_auth(&admin2, &user2_id, false);
+    assert!(!token.authorized(&user2_id));
     assert_eq!(token.nonce(&admin2_id), 2);
 
+    token.set_auth(&admin2, &user3_id, true);
+    assert!(token.authorized(&user3_id));
+    assert_eq!(token.nonce(&admin2_id), 3);
+
     token.clawback(&admin2, &user3_id, &100);
     assert_eq!(token.balance(&user3_id), 200);
-    assert_eq!(token.nonce(&admin2_id), 3);
+    assert_eq!(token.nonce(&admin2_id), 4);
 
     // Increase by 400, with an existing 100 = 500
     token.incr_allow(&user2, &user3_id, &400);
@@ -109,7 +148,7 @@ fn test_burn() {
 }
 
 #[test]
-#[should_panic(expected = "insufficient balance")]
+#[should_panic(expected = "Status(ContractError(5))")]
 fn xfer_insufficient_balance() {
     let e: Env = Default::default();
     let contract_id = register_token(&e);
@@ -132,7 +171,7 @@ fn xfer_insufficient_balance() {
 }
 
 #[test]
-#[should_panic(expected = "can't receive when deauthorized")]
+#[should_panic(expected = "Status(ContractError(7))")]
 fn xfer_receive_deauthorized() {
     let e: Env = Default::default();
     let contract_id = register_token(&e);
@@ -156,7 +195,7 @@ fn xfer_receive_deauthorized() {
 }
 
 #[test]
-#[should_panic(expected = "can't spend when deauthorized")]
+#[should_panic(expected = "Status(ContractError(8))")]
 fn xfer_spend_deauthorized() {
     let e: Env = Default::default();
     let contract_id = register_token(&e);
@@ -180,7 +219,7 @@ fn xfer_spend_deauthorized() {
 }
 
 #[test]
-#[should_panic(expected = "insufficient allowance")]
+#[should_panic(expected = "Status(ContractError(6))")]
 fn xfer_from_insufficient_allowance() {
     let e: Env = Default::default();
     let contract_id = register_token(&e);
@@ -209,7 +248,7 @@ fn xfer_from_insufficient_allowance() {
 }
 
 #[test]
-#[should_panic(expected = "already initialized")]
+#[should_panic(expected = "Status(ContractError(1))")]
 fn initialize_already_initialized() {
     let e: Env = Default::default();
     let contract_id = register_token(&e);
@@ -250,7 +289,7 @@ fn set_admin_bad_signature() {
 }
 
 #[test]
-#[should_panic(expected = "Decimal must fit in a u8")]
+#[should_panic(expected = "Status(ContractError(2))")]
 fn decimal_is_over_max() {
     let e = Default::default();
     let contract_id = register_token(&e);
@@ -261,3 +300,1168 @@ fn decimal_is_over_max() {
 
     token.initialize(&admin1_id, u32::from(u8::MAX) + 1, "name", "symbol");
 }
+
+#[test]
+fn test_vesting() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+
+    e.ledger().with_mut(|li| li.sequence_number = 100);
+    let grant_id = token.grant_vest(&admin1, &user1_id, &1000, 100, 10, 100);
+    assert_eq!(token.nonce(&admin1_id), 1);
+
+    let grant = token.vesting_of(grant_id).unwrap();
+    assert_eq!(grant.recipient, user1_id);
+    assert_eq!(grant.total, 1000);
+    assert_eq!(grant.claimed, 0);
+
+    // Past the cliff, a quarter of the duration has elapsed
+    e.ledger().with_mut(|li| li.sequence_number = 125);
+    assert_eq!(token.claim_vest(&user1_id), 250);
+    assert_eq!(token.balance(&user1_id), 250);
+
+    e.ledger().with_mut(|li| li.sequence_number = 150);
+    assert_eq!(token.claim_vest(&user1_id), 250);
+    assert_eq!(token.balance(&user1_id), 500);
+
+    // Fully vested, claims stop at the total
+    e.ledger().with_mut(|li| li.sequence_number = 1000);
+    assert_eq!(token.claim_vest(&user1_id), 500);
+    assert_eq!(token.balance(&user1_id), 1000);
+    assert_eq!(token.vesting_of(grant_id).unwrap().claimed, 1000);
+}
+
+#[test]
+fn test_vesting_several_grants() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+
+    e.ledger().with_mut(|li| li.sequence_number = 100);
+    let first = token.grant_vest(&admin1, &user1_id, &1000, 100, 10, 100);
+    let other = token.grant_vest(&admin1, &user2_id, &500, 100, 0, 100);
+    let second = token.grant_vest(&admin1, &user1_id, &400, 150, 0, 200);
+    assert_eq!((first, other, second), (0, 1, 2));
+    assert_eq!(token.grants_of(&user1_id), vec![&e, first, second]);
+    assert_eq!(token.grants_of(&user2_id), vec![&e, other]);
+
+    // Only the first grant has started
+    e.ledger().with_mut(|li| li.sequence_number = 150);
+    assert_eq!(token.claim_vest(&user1_id), 500);
+
+    // Each grant vests on its own schedule
+    e.ledger().with_mut(|li| li.sequence_number = 200);
+    assert_eq!(token.claim_vest(&user1_id), 600);
+    assert_eq!(token.vesting_of(first).unwrap().claimed, 1000);
+    assert_eq!(token.vesting_of(second).unwrap().claimed, 100);
+    assert_eq!(token.balance(&user1_id), 1100);
+    assert_eq!(token.balance(&user2_id), 0);
+    // the first grant is done and no longer walked by claims
+    assert_eq!(token.grants_of(&user1_id), vec![&e, second]);
+
+    e.ledger().with_mut(|li| li.sequence_number = 400);
+    assert_eq!(token.claim_vest(&user1_id), 300);
+    assert_eq!(token.grants_of(&user1_id), soroban_sdk::Vec::new(&e));
+    assert_eq!(token.vesting_of(second).unwrap().claimed, 400);
+    assert_eq!(
+        TokenClient::new(&e, &contract_id).try_claim_vest(&user1_id),
+        Err(Ok(Error::NoVesting.into()))
+    );
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(21))")]
+fn claim_vest_before_cliff() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+
+    e.ledger().with_mut(|li| li.sequence_number = 100);
+    token.grant_vest(&admin1, &user1_id, &1000, 100, 10, 100);
+
+    e.ledger().with_mut(|li| li.sequence_number = 109);
+    token.claim_vest(&user1_id);
+}
+
+#[test]
+fn test_roles() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let minter = generate_keypair();
+    let minter_id = to_ed25519(&e, &minter);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    assert!(token.has_role(Role::Minter, &admin1_id));
+    assert!(token.has_role(Role::Pauser, &admin1_id));
+    assert!(token.has_role(Role::Compliance, &admin1_id));
+    assert!(token.has_role(Role::Admin, &admin1_id));
+
+    token.add_role(&admin1, Role::Minter, &minter_id);
+    assert!(token.has_role(Role::Minter, &minter_id));
+    assert!(!token.has_role(Role::Compliance, &minter_id));
+    assert_eq!(token.members(Role::Minter).len(), 2);
+    assert_eq!(token.nonce(&admin1_id), 1);
+
+    token.mint(&minter, &user1_id, &1000);
+    assert_eq!(token.balance(&user1_id), 1000);
+    assert_eq!(token.nonce(&minter_id), 1);
+
+    token.del_role(&admin1, Role::Minter, &admin1_id);
+    assert!(!token.has_role(Role::Minter, &admin1_id));
+    assert_eq!(token.members(Role::Minter).len(), 1);
+    assert_eq!(token.nonce(&admin1_id), 2);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(3))")]
+fn minter_cannot_clawback() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let minter = generate_keypair();
+    let minter_id = to_ed25519(&e, &minter);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.add_role(&admin1, Role::Minter, &minter_id);
+    token.mint(&minter, &user1_id, &1000);
+
+    token.clawback(&minter, &user1_id, &1000);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(11))")]
+fn del_role_last_role_admin() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.del_role(&admin1, Role::Admin, &admin1_id);
+}
+
+#[test]
+fn test_pause() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let pauser = generate_keypair();
+    let pauser_id = to_ed25519(&e, &pauser);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.add_role(&admin1, Role::Pauser, &pauser_id);
+    token.mint(&admin1, &user1_id, &1000);
+    assert!(!token.is_paused());
+
+    token.pause(&pauser);
+    assert!(token.is_paused());
+    assert_eq!(token.nonce(&pauser_id), 1);
+
+    // Read-only functions keep working while paused
+    assert_eq!(token.balance(&user1_id), 1000);
+    assert_eq!(token.allowance(&user1_id, &user2_id), 0);
+
+    token.unpause(&admin1);
+    assert!(!token.is_paused());
+
+    token.xfer(&user1, &user2_id, &400);
+    assert_eq!(token.balance(&user1_id), 600);
+    assert_eq!(token.balance(&user2_id), 400);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(10))")]
+fn xfer_while_paused() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.mint(&admin1, &user1_id, &1000);
+
+    token.pause(&admin1);
+    token.xfer(&user1, &user2_id, &1);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(10))")]
+fn mint_while_paused() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+
+    token.pause(&admin1);
+    token.mint(&admin1, &user1_id, &1000);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(3))")]
+fn pause_without_role() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.pause(&user1);
+}
+
+#[test]
+fn test_admin_transfer() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let admin2 = generate_keypair();
+    let admin2_id = to_ed25519(&e, &admin2);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+
+    // Nominating doesn't hand anything over yet
+    token.set_admin(&admin1, &admin2_id);
+    assert!(token.has_role(Role::Admin, &admin1_id));
+    assert!(!token.has_role(Role::Admin, &admin2_id));
+
+    token.cancel_adm(&admin1);
+    assert_eq!(token.nominee(), None);
+    assert_eq!(token.nonce(&admin1_id), 2);
+
+    token.set_admin(&admin1, &admin2_id);
+    token.accept_adm(&admin2);
+    assert_eq!(token.members(Role::Admin).len(), 1);
+    assert!(token.has_role(Role::Admin, &admin2_id));
+    assert!(token.has_role(Role::Minter, &admin2_id));
+    assert!(!token.has_role(Role::Admin, &admin1_id));
+    assert!(!token.has_role(Role::Minter, &admin1_id));
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(13))")]
+fn accept_adm_not_nominee() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let admin2 = generate_keypair();
+    let admin2_id = to_ed25519(&e, &admin2);
+    let user1 = generate_keypair();
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.set_admin(&admin1, &admin2_id);
+    token.accept_adm(&user1);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(12))")]
+fn accept_adm_after_cancel() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let admin2 = generate_keypair();
+    let admin2_id = to_ed25519(&e, &admin2);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.set_admin(&admin1, &admin2_id);
+    token.cancel_adm(&admin1);
+    token.accept_adm(&admin2);
+}
+
+#[test]
+fn test_permit() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+    let user3 = generate_keypair();
+    let user3_id = to_ed25519(&e, &user3);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.mint(&admin1, &user1_id, &1000);
+
+    e.ledger().with_mut(|li| li.sequence_number = 10);
+    token.permit(&user1, &user2_id, &500, 10);
+    assert_eq!(token.allowance(&user1_id, &user2_id), 500);
+    assert_eq!(token.nonce(&user1_id), 1);
+
+    token.xfer_from(&user2, &user1_id, &user3_id, &300);
+    assert_eq!(token.balance(&user3_id), 300);
+    assert_eq!(token.allowance(&user1_id, &user2_id), 200);
+
+    // A permit sets the allowance rather than adding to it
+    token.permit(&user1, &user2_id, &50, 20);
+    assert_eq!(token.allowance(&user1_id, &user2_id), 50);
+    assert_eq!(token.nonce(&user1_id), 2);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(15))")]
+fn permit_expired() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let user1 = generate_keypair();
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+
+    e.ledger().with_mut(|li| li.sequence_number = 11);
+    token.permit(&user1, &user2_id, &500, 10);
+}
+
+#[test]
+#[should_panic]
+fn permit_replay() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+
+    let client = TokenClient::new(&e, &contract_id);
+    let msg = SignaturePayload::V0(SignaturePayloadV0 {
+        name: symbol!("permit"),
+        contract: contract_id.clone(),
+        network: e.ledger().network_passphrase(),
+        args: (&user1_id, &0i128, &user2_id, &500i128, 10u32).into_val(&e),
+    });
+    let signature: BytesN<64> = user1.sign(msg).unwrap().into_val(&e);
+
+    client.permit(&user1_id, &user2_id, &500, &10, &signature);
+    client.permit(&user1_id, &user2_id, &500, &10, &signature);
+}
+
+#[test]
+fn test_xfer_call() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+    let receiver_id = e.register_contract(None, Receiver);
+    let receiver = Identifier::Contract(receiver_id.clone());
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.mint(&admin1, &user1_id, &1000);
+
+    let data = Bytes::from_slice(&e, b"deposit");
+    token.xfer_call(&user1, &receiver_id, &400, &data);
+    assert_eq!(token.balance(&user1_id), 600);
+    assert_eq!(token.balance(&receiver), 400);
+    assert_eq!(token.nonce(&user1_id), 1);
+
+    e.as_contract(&receiver_id, || {
+        let from: Identifier = e.storage().get_unchecked(symbol!("from")).unwrap();
+        let amount: i128 = e.storage().get_unchecked(symbol!("amount")).unwrap();
+        let received: Bytes = e.storage().get_unchecked(symbol!("data")).unwrap();
+        assert_eq!(from, user1_id);
+        assert_eq!(amount, 400);
+        assert_eq!(received, data);
+    });
+}
+
+#[test]
+fn xfer_call_rejected_reverts() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+    let receiver_id = e.register_contract(None, Receiver);
+    let receiver = Identifier::Contract(receiver_id.clone());
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.mint(&admin1, &user1_id, &1000);
+
+    let data = Bytes::from_slice(&e, b"reject");
+    let nonce = token.nonce(&user1_id);
+    let msg = SignaturePayload::V0(SignaturePayloadV0 {
+        name: symbol!("xfer_call"),
+        contract: contract_id.clone(),
+        network: e.ledger().network_passphrase(),
+        args: (&user1_id, &nonce, &receiver_id, &400i128, &data).into_val(&e),
+    });
+    let auth = Signature::Ed25519(Ed25519Signature {
+        public_key: user1.public.to_bytes().into_val(&e),
+        signature: user1.sign(msg).unwrap().into_val(&e),
+    });
+
+    let client = TokenClient::new(&e, &contract_id);
+    assert!(client
+        .try_xfer_call(&auth, &nonce, &receiver_id, &400, &data)
+        .is_err());
+    assert_eq!(token.balance(&user1_id), 1000);
+    assert_eq!(token.balance(&receiver), 0);
+    assert_eq!(token.nonce(&user1_id), 0);
+}
+
+#[test]
+fn test_xfer_memo() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.mint(&admin1, &user1_id, &1000);
+
+    let memo = Bytes::from_slice(&e, b"GDEST...:ref-42");
+    token.xfer_memo(&user1, &user2_id, &400, &memo);
+    assert_eq!(token.balance(&user1_id), 600);
+    assert_eq!(token.balance(&user2_id), 400);
+    assert_eq!(token.nonce(&user1_id), 1);
+
+    let (event_contract, topics, data) = e.events().all().last().unwrap().unwrap();
+    let expected_topics: soroban_sdk::Vec<RawVal> =
+        (symbol!("transfer"), &user1_id, &user2_id).into_val(&e);
+    let (amount, received): (i128, Bytes) = data.try_into_val(&e).unwrap();
+    assert_eq!(event_contract, contract_id);
+    assert_eq!(topics, expected_topics);
+    assert_eq!(amount, 400);
+    assert_eq!(received, memo);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(17))")]
+fn xfer_memo_too_long() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.mint(&admin1, &user1_id, &1000);
+
+    let memo = Bytes::from_array(&e, &[0; 129]);
+    token.xfer_memo(&user1, &user2_id, &400, &memo);
+}
+
+#[test]
+fn test_typed_errors() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+    let client = TokenClient::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+
+    let name: Bytes = "name".into_val(&e);
+    let symbol: Bytes = "symbol".into_val(&e);
+    assert_eq!(
+        client.try_initialize(&admin1_id, &7, &name, &symbol, &AUTH_REVOCABLE),
+        Err(Ok(Error::AlreadyInitialized.into()))
+    );
+    assert_eq!(
+        client.try_claim_vest(&user1_id),
+        Err(Ok(Error::NoVesting.into()))
+    );
+    assert_eq!(
+        client.try_xfer(&Signature::Invoker, &1, &user1_id, &1),
+        Err(Ok(Error::IncorrectNonce.into()))
+    );
+}
+
+#[test]
+fn negative_amounts_rejected() {
+    let e: Env = Default::default();
+    e.budget().reset();
+    let contract_id = register_token(&e);
+    let client = TokenClient::new(&e, &contract_id);
+
+    let admin = generate_account(&e);
+    let user = generate_account(&e);
+    let name: Bytes = "name".into_val(&e);
+    let symbol: Bytes = "symbol".into_val(&e);
+    client.initialize(&admin, &7, &name, &symbol, &AUTH_REVOCABLE);
+
+    invoke_as(&e, &admin);
+    client.mint(&Signature::Invoker, &0, &admin, &1000);
+    client.incr_allow(&Signature::Invoker, &0, &admin, &1000);
+
+    let mut amounts = std::vec![i128::MIN, -1];
+    for _ in 0..20 {
+        amounts.push(-thread_rng().gen_range(1, i128::MAX));
+    }
+
+    let err = Err(Ok(Error::NegativeAmount.into()));
+    let inv = Signature::Invoker;
+    for amount in amounts {
+        assert_eq!(client.try_xfer(&inv, &0, &user, &amount), err);
+        assert_eq!(client.try_xfer_from(&inv, &0, &admin, &user, &amount), err);
+        assert_eq!(client.try_burn(&inv, &0, &amount), err);
+        assert_eq!(client.try_burn_from(&inv, &0, &admin, &amount), err);
+        assert_eq!(client.try_clawback(&inv, &0, &admin, &amount), err);
+        assert_eq!(client.try_mint(&inv, &0, &user, &amount), err);
+        assert_eq!(client.try_incr_allow(&inv, &0, &user, &amount), err);
+        assert_eq!(client.try_decr_allow(&inv, &0, &user, &amount), err);
+    }
+
+    assert_eq!(client.balance(&admin), 1000);
+    assert_eq!(client.balance(&user), 0);
+    assert_eq!(client.allowance(&admin, &admin), 1000);
+}
+
+#[test]
+fn balance_overflow_rejected() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let client = TokenClient::new(&e, &contract_id);
+
+    let admin = generate_account(&e);
+    let user = generate_account(&e);
+    let name: Bytes = "name".into_val(&e);
+    let symbol: Bytes = "symbol".into_val(&e);
+    client.initialize(&admin, &7, &name, &symbol, &AUTH_REVOCABLE);
+
+    invoke_as(&e, &admin);
+    client.mint(&Signature::Invoker, &0, &user, &i128::MAX);
+    assert_eq!(
+        client.try_mint(&Signature::Invoker, &0, &user, &1),
+        Err(Ok(Error::Overflow.into()))
+    );
+
+    client.mint(&Signature::Invoker, &0, &admin, &1);
+    assert_eq!(
+        client.try_xfer(&Signature::Invoker, &0, &user, &1),
+        Err(Ok(Error::Overflow.into()))
+    );
+    assert_eq!(client.balance(&user), i128::MAX);
+    assert_eq!(client.balance(&admin), 1);
+}
+
+#[test]
+fn random_xfers_conserve_supply() {
+    let e: Env = Default::default();
+    e.budget().reset();
+    let contract_id = register_token(&e);
+    let client = TokenClient::new(&e, &contract_id);
+    let mut rng = thread_rng();
+
+    let admin = generate_account(&e);
+    let name: Bytes = "name".into_val(&e);
+    let symbol: Bytes = "symbol".into_val(&e);
+    client.initialize(&admin, &7, &name, &symbol, &AUTH_REVOCABLE);
+
+    let users: std::vec::Vec<Identifier> = (0..4).map(|_| generate_account(&e)).collect();
+    invoke_as(&e, &admin);
+    for user in users.iter() {
+        client.mint(&Signature::Invoker, &0, user, &1000);
+    }
+
+    for _ in 0..100 {
+        let from = &users[rng.gen_range(0, users.len())];
+        let to = &users[rng.gen_range(0, users.len())];
+        let amount = rng.gen_range(0, 1500);
+        let balance = client.balance(from);
+
+        invoke_as(&e, from);
+        let result = client.try_xfer(&Signature::Invoker, &0, to, &amount);
+        if amount > balance {
+            assert_eq!(result, Err(Ok(Error::InsufficientBalance.into())));
+        } else {
+            assert!(result.is_ok());
+        }
+
+        let balances = users.iter().map(|user| client.balance(user));
+        assert!(balances.clone().all(|balance| balance >= 0));
+        assert_eq!(balances.sum::<i128>(), 4000);
+    }
+}
+
+#[test]
+fn test_holders() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+    let user3 = generate_keypair();
+    let user3_id = to_ed25519(&e, &user3);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    assert_eq!(token.holder_cnt(), 0);
+
+    token.mint(&admin1, &user1_id, &1000);
+    token.mint(&admin1, &user2_id, &1000);
+    token.xfer(&user1, &user3_id, &500);
+    assert_eq!(token.holder_cnt(), 3);
+    assert_eq!(
+        token.holders(0, 10),
+        vec![&e, user1_id.clone(), user2_id.clone(), user3_id.clone()]
+    );
+    assert_eq!(token.holders(1, 1), vec![&e, user2_id.clone()]);
+    assert_eq!(token.holders(3, 10).len(), 0);
+
+    // Emptying a balance drops the holder, the last one takes its slot
+    token.xfer(&user1, &user2_id, &500);
+    assert_eq!(token.holder_cnt(), 2);
+    assert_eq!(
+        token.holders(0, 10),
+        vec![&e, user3_id.clone(), user2_id.clone()]
+    );
+
+    token.burn(&user3, &500);
+    token.xfer(&user2, &user1_id, &1);
+    assert_eq!(token.holder_cnt(), 2);
+    assert_eq!(token.holders(0, 10), vec![&e, user2_id, user1_id]);
+}
+
+#[test]
+fn holders_page_is_capped() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    // the test env charges every call to one budget, this many mints exhaust it
+    e.budget().reset();
+    for _ in 0..=MAX_HOLDERS_PAGE {
+        let user = generate_keypair();
+        token.mint(&admin1, &to_ed25519(&e, &user), &1);
+    }
+    assert_eq!(token.holder_cnt(), MAX_HOLDERS_PAGE + 1);
+
+    assert_eq!(token.holders(0, u32::MAX).len(), MAX_HOLDERS_PAGE);
+    assert_eq!(token.holders(MAX_HOLDERS_PAGE, u32::MAX).len(), 1);
+}
+
+#[test]
+fn test_reindex() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+    let user3 = generate_keypair();
+    let user3_id = to_ed25519(&e, &user3);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    // balances as they were written before the holder index existed
+    e.as_contract(&contract_id, || {
+        e.storage()
+            .set(DataKey::Balance(user1_id.clone()), 1000_i128);
+        e.storage().set(DataKey::Balance(user2_id.clone()), 5_i128);
+        e.storage().set(DataKey::Balance(user3_id.clone()), 0_i128);
+    });
+    assert_eq!(token.holder_cnt(), 0);
+
+    let ids = vec![&e, user1_id.clone(), user2_id.clone(), user3_id.clone()];
+    let client = TokenClient::new(&e, &contract_id);
+    assert_eq!(
+        client.try_reindex(&Signature::Invoker, &0, &ids),
+        Err(Ok(Error::NotAuthorized.into()))
+    );
+
+    token.reindex(&admin1, &ids);
+    assert_eq!(token.holder_cnt(), 2);
+    assert_eq!(
+        token.holders(0, 10),
+        vec![&e, user1_id.clone(), user2_id.clone()]
+    );
+
+    // already indexed holders aren't listed twice
+    token.reindex(&admin1, &ids);
+    assert_eq!(token.holder_cnt(), 2);
+
+    // and they leave the index like any other holder
+    token.xfer(&user2, &user1_id, &5);
+    assert_eq!(token.holders(0, 10), vec![&e, user1_id]);
+}
+
+#[test]
+fn test_fee() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let treasury = generate_keypair();
+    let treasury_id = to_ed25519(&e, &treasury);
+    let bridge = generate_keypair();
+    let bridge_id = to_ed25519(&e, &bridge);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    assert_eq!(token.fee_bps(), 0);
+    assert_eq!(token.treasury(), None);
+
+    token.mint(&admin1, &user1_id, &10000);
+    token.set_fee(&admin1, 100, &treasury_id);
+    token.set_exempt(&admin1, &bridge_id, true);
+    assert_eq!(token.fee_bps(), 100);
+    assert_eq!(token.treasury(), Some(treasury_id.clone()));
+    assert!(token.is_exempt(&bridge_id));
+    assert!(!token.is_exempt(&user1_id));
+
+    // 1% of the transfer goes to the treasury
+    token.xfer(&user1, &user2_id, &1000);
+    assert_eq!(token.balance(&user1_id), 9000);
+    assert_eq!(token.balance(&user2_id), 990);
+    assert_eq!(token.balance(&treasury_id), 10);
+
+    token.incr_allow(&user1, &user2_id, &2000);
+    token.xfer_from(&user2, &user1_id, &user2_id, &2000);
+    assert_eq!(token.balance(&user1_id), 7000);
+    assert_eq!(token.balance(&user2_id), 2970);
+    assert_eq!(token.balance(&treasury_id), 30);
+
+    // Too small to be charged
+    token.xfer(&user1, &user2_id, &99);
+    assert_eq!(token.balance(&user2_id), 3069);
+    assert_eq!(token.balance(&treasury_id), 30);
+
+    // Exempt identifiers and the treasury itself don't pay
+    token.xfer(&user1, &bridge_id, &1000);
+    assert_eq!(token.balance(&bridge_id), 1000);
+    token.xfer(&bridge, &user1_id, &500);
+    assert_eq!(token.balance(&bridge_id), 500);
+    token.xfer(&treasury, &user2_id, &30);
+    assert_eq!(token.balance(&treasury_id), 0);
+    assert_eq!(token.balance(&user2_id), 3099);
+
+    token.set_exempt(&admin1, &bridge_id, false);
+    token.xfer(&bridge, &user1_id, &500);
+    assert_eq!(token.balance(&treasury_id), 5);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(23))")]
+fn set_fee_too_high() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let treasury = generate_keypair();
+    let treasury_id = to_ed25519(&e, &treasury);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.set_fee(&admin1, MAX_FEE_BPS, &treasury_id);
+    assert_eq!(token.fee_bps(), MAX_FEE_BPS);
+    token.set_fee(&admin1, MAX_FEE_BPS + 1, &treasury_id);
+}
+
+#[test]
+fn test_auth_required() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+
+    token.initialize_with_flags(&admin1_id, 7, "name", "symbol", AUTH_REQUIRED);
+    assert_eq!(token.auth_flags(), AUTH_REQUIRED);
+    assert!(!token.authorized(&user1_id));
+    assert!(!token.authorized(&user2_id));
+
+    token.auth_batch(&admin1, &vec![&e, user1_id.clone(), user2_id.clone()]);
+    assert!(token.authorized(&user1_id));
+    assert!(token.authorized(&user2_id));
+
+    token.mint(&admin1, &user1_id, &1000);
+    token.xfer(&user1, &user2_id, &400);
+    assert_eq!(token.balance(&user1_id), 600);
+    assert_eq!(token.balance(&user2_id), 400);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(7))")]
+fn mint_to_unauthorized_rejected() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize_with_flags(&admin1_id, 7, "name", "symbol", AUTH_REQUIRED);
+    token.mint(&admin1, &user1_id, &1000);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(25))")]
+fn revoke_without_revocable_rejected() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize_with_flags(&admin1_id, 7, "name", "symbol", 0);
+    token.set_auth(&admin1, &user1_id, false);
+}
+
+#[test]
+fn test_auth_revocable() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+
+    token.initialize_with_flags(
+        &admin1_id,
+        7,
+        "name",
+        "symbol",
+        AUTH_REQUIRED | AUTH_REVOCABLE,
+    );
+    token.set_auth(&admin1, &user1_id, true);
+    assert!(token.authorized(&user1_id));
+    token.set_auth(&admin1, &user1_id, false);
+    assert!(!token.authorized(&user1_id));
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(24))")]
+fn initialize_invalid_flags() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+
+    token.initialize_with_flags(&admin1_id, 7, "name", "symbol", 4);
+}
+
+#[test]
+fn test_metadata() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    let meta = token.metadata();
+    assert_eq!(meta.decimals, 7);
+    assert_eq!(meta.name, Bytes::from_slice(&e, b"name"));
+    assert_eq!(meta.symbol, Bytes::from_slice(&e, b"symbol"));
+    assert!(meta.desc.is_empty());
+    assert!(meta.remote_id.is_empty());
+
+    let info = TokenInfo {
+        desc: Bytes::from_slice(&e, b"Bridged DAO token"),
+        domain: Bytes::from_slice(&e, b"dao.example.org"),
+        icon_uri: Bytes::from_slice(&e, b"https://dao.example.org/icon.png"),
+        remote_id: Bytes::from_slice(&e, b"0x5f4ec3df9cbd43714fe2740f5e3616155c5b8419"),
+    };
+    token.set_info(&admin1, &info);
+    let meta = token.metadata();
+    assert_eq!(meta.decimals, 7);
+    assert_eq!(meta.desc, info.desc);
+    assert_eq!(meta.domain, info.domain);
+    assert_eq!(meta.icon_uri, info.icon_uri);
+    assert_eq!(meta.remote_id, info.remote_id);
+
+    let (_, topics, data) = e.events().all().last().unwrap().unwrap();
+    let expected_topics: soroban_sdk::Vec<RawVal> = (symbol!("set_info"), &admin1_id).into_val(&e);
+    let emitted: TokenInfo = data.try_into_val(&e).unwrap();
+    assert_eq!(topics, expected_topics);
+    assert_eq!(emitted, info);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(3))")]
+fn set_info_not_admin() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    let info = TokenInfo {
+        desc: Bytes::new(&e),
+        domain: Bytes::new(&e),
+        icon_uri: Bytes::new(&e),
+        remote_id: Bytes::new(&e),
+    };
+    token.set_info(&user1, &info);
+}
+
+#[test]
+fn test_migrate() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+    let client = TokenClient::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let account = generate_account(&e);
+
+    // What the v0 contract's initialize and a mint left in storage
+    e.as_contract(&contract_id, || {
+        e.storage().set(DataKey::Admin, admin1_id.clone());
+        e.storage().set(DataKey::Decimals, 7_u32);
+        e.storage()
+            .set(DataKey::Name, Bytes::from_slice(&e, b"name"));
+        e.storage()
+            .set(DataKey::Symbol, Bytes::from_slice(&e, b"symbol"));
+        e.storage()
+            .set(DataKey::Balance(user1_id.clone()), 1000_i128);
+    });
+    assert_eq!(token.version(), 0);
+
+    // Nobody can take the unmigrated contract over
+    let name: Bytes = "name".into_val(&e);
+    let symbol: Bytes = "symbol".into_val(&e);
+    assert_eq!(
+        client.try_initialize(&account, &7, &name, &symbol, &AUTH_REVOCABLE),
+        Err(Ok(Error::AlreadyInitialized.into()))
+    );
+    invoke_as(&e, &account);
+    assert_eq!(
+        client.try_migrate(&Signature::Invoker, &0),
+        Err(Ok(Error::NotAuthorized.into()))
+    );
+
+    token.migrate(&admin1);
+    assert_eq!(token.version(), CONTRACT_VERSION);
+    assert_eq!(token.auth_flags(), AUTH_REVOCABLE);
+    assert!(token.has_role(Role::Admin, &admin1_id));
+    assert!(token.has_role(Role::Minter, &admin1_id));
+    e.as_contract(&contract_id, || {
+        assert!(!e.storage().has(DataKey::Admin));
+    });
+    let (_, _, data) = e.events().all().last().unwrap().unwrap();
+    let (from, to): (u32, u32) = data.try_into_val(&e).unwrap();
+    assert_eq!((from, to), (0, CONTRACT_VERSION));
+
+    // The v0 administrator keeps running the token
+    token.mint(&admin1, &user1_id, &500);
+    assert_eq!(token.balance(&user1_id), 1500);
+    assert_eq!(token.decimals(), 7);
+
+    // Already current, nothing to do
+    token.migrate(&admin1);
+    assert_eq!(token.version(), CONTRACT_VERSION);
+}
+
+#[test]
+fn test_v0_allowance_migration() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+    let user3 = generate_keypair();
+    let user3_id = to_ed25519(&e, &user3);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+    token.mint(&admin1, &user1_id, &1000);
+
+    // Seed v0 storage: no version key and allowances stored as bare i128
+    let key12 = AllowanceDataKey {
+        from: user1_id.clone(),
+        spender: user2_id.clone(),
+    };
+    let key13 = AllowanceDataKey {
+        from: user1_id.clone(),
+        spender: user3_id.clone(),
+    };
+    e.as_contract(&contract_id, || {
+        e.storage().remove(DataKey::Version);
+        e.storage().set(DataKey::Allowance(key12.clone()), 500_i128);
+        e.storage().set(DataKey::Allowance(key13.clone()), 300_i128);
+    });
+
+    token.migrate(&admin1);
+    assert_eq!(token.version(), CONTRACT_VERSION);
+
+    // Reading falls back to the old entry and leaves storage alone
+    assert_eq!(token.allowance(&user1_id, &user2_id), 500);
+    e.as_contract(&contract_id, || {
+        assert!(e.storage().has(DataKey::Allowance(key12.clone())));
+        assert!(!e.storage().has(DataKey::Allow(key12.clone())));
+    });
+
+    // Spending works straight off an unmigrated entry
+    token.xfer_from(&user3, &user1_id, &user3_id, &100);
+    assert_eq!(token.allowance(&user1_id, &user3_id), 200);
+    assert_eq!(token.balance(&user3_id), 100);
+    e.as_contract(&contract_id, || {
+        assert!(!e.storage().has(DataKey::Allowance(key13)));
+    });
+
+    // Spending moves the entry to the new format
+    token.xfer_from(&user2, &user1_id, &user2_id, &500);
+    assert_eq!(token.allowance(&user1_id, &user2_id), 0);
+    e.as_contract(&contract_id, || {
+        assert!(!e.storage().has(DataKey::Allowance(key12.clone())));
+        let value: AllowanceValue = e.storage().get_unchecked(DataKey::Allow(key12)).unwrap();
+        assert_eq!(value, AllowanceValue { amount: 0 });
+    });
+    assert_eq!(token.balance(&user1_id), 400);
+}
+
+#[test]
+fn test_v0_allowance_overwritten() {
+    let e: Env = Default::default();
+    let contract_id = register_token(&e);
+    let token = Token::new(&e, &contract_id);
+
+    let admin1 = generate_keypair();
+    let admin1_id = to_ed25519(&e, &admin1);
+    let user1 = generate_keypair();
+    let user1_id = to_ed25519(&e, &user1);
+    let user2 = generate_keypair();
+    let user2_id = to_ed25519(&e, &user2);
+
+    token.initialize(&admin1_id, 7, "name", "symbol");
+
+    let key = AllowanceDataKey {
+        from: user1_id.clone(),
+        spender: user2_id.clone(),
+    };
+    e.as_contract(&contract_id, || {
+        e.storage().set(DataKey::Allowance(key.clone()), 500_i128);
+    });
+
+    token.decr_allow(&user1, &user2_id, &200);
+    assert_eq!(token.allowance(&user1_id, &user2_id), 300);
+    e.as_contract(&contract_id, || {
+        assert!(!e.storage().has(DataKey::Allowance(key)));
+    });
+}
@@ -1,10 +1,12 @@
 #![cfg(any(test, feature = "testutils"))]
 
+use crate::balance::AUTH_REVOCABLE;
 use crate::contract::TokenClient;
+use crate::storage_types::{Metadata, Role, TokenInfo, VestingGrant};
 use ed25519_dalek::Keypair;
 use soroban_auth::{Ed25519Signature, Identifier, Signature, SignaturePayload, SignaturePayloadV0};
 use soroban_sdk::testutils::ed25519::Sign;
-use soroban_sdk::{symbol, Bytes, BytesN, Env, IntoVal};
+use soroban_sdk::{symbol, Bytes, BytesN, Env, IntoVal, Vec};
 
 pub fn register_test_contract(e: &Env) -> BytesN<32> {
     e.register_contract(None, crate::contract::Token {})
@@ -28,9 +30,21 @@ impl Token {
     }
 
     pub fn initialize(&self, admin: &Identifier, decimals: u32, name: &str, symbol: &str) {
+        self.initialize_with_flags(admin, decimals, name, symbol, AUTH_REVOCABLE)
+    }
+
+    pub fn initialize_with_flags(
+        &self,
+        admin: &Identifier,
+        decimals: u32,
+        name: &str,
+        symbol: &str,
+        flags: u32,
+    ) {
         let name: Bytes = name.into_val(&self.env);
         let symbol: Bytes = symbol.into_val(&self.env);
-        TokenClient::new(&self.env, &self.contract_id).initialize(admin, &decimals, &name, &symbol);
+        TokenClient::new(&self.env, &self.contract_id)
+            .initialize(admin, &decimals, &name, &symbol, &flags);
     }
 
     pub fn nonce(&self, id: &Identifier) -> i128 {
@@ -77,6 +91,21 @@ impl Token {
         TokenClient::new(&self.env, &self.contract_id).decr_allow(&auth, &nonce, spender, amount)
     }
 
+    pub fn permit(&self, owner: &Keypair, spender: &Identifier, amount: &i128, deadline: u32) {
+        let owner_id = to_ed25519(&self.env, owner);
+        let nonce = self.nonce(&owner_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("permit"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (&owner_id, &nonce, spender, amount, deadline).into_val(&self.env),
+        });
+        let signature: BytesN<64> = owner.sign(msg).unwrap().into_val(&self.env);
+        TokenClient::new(&self.env, &self.contract_id)
+            .permit(&owner_id, spender, amount, &deadline, &signature)
+    }
+
     pub fn balance(&self, id: &Identifier) -> i128 {
         TokenClient::new(&self.env, &self.contract_id).balance(id)
     }
@@ -123,6 +152,50 @@ impl Token {
         TokenClient::new(&self.env, &self.contract_id).xfer_from(&auth, &nonce, from, to, amount)
     }
 
+    pub fn xfer_call(&self, from: &Keypair, to_contract: &BytesN<32>, amount: &i128, data: &Bytes) {
+        let from_id = to_ed25519(&self.env, from);
+        let nonce = self.nonce(&from_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("xfer_call"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (from_id, &nonce, to_contract, amount, data).into_val(&self.env),
+        });
+
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: BytesN::from_array(&self.env, &from.public.to_bytes()),
+            signature: from.sign(msg).unwrap().into_val(&self.env),
+        });
+
+        TokenClient::new(&self.env, &self.contract_id).xfer_call(
+            &auth,
+            &nonce,
+            to_contract,
+            amount,
+            data,
+        )
+    }
+
+    pub fn xfer_memo(&self, from: &Keypair, to: &Identifier, amount: &i128, memo: &Bytes) {
+        let from_id = to_ed25519(&self.env, from);
+        let nonce = self.nonce(&from_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("xfer_memo"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (from_id, &nonce, to, amount, memo).into_val(&self.env),
+        });
+
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: BytesN::from_array(&self.env, &from.public.to_bytes()),
+            signature: from.sign(msg).unwrap().into_val(&self.env),
+        });
+
+        TokenClient::new(&self.env, &self.contract_id).xfer_memo(&auth, &nonce, to, amount, memo)
+    }
+
     pub fn burn(&self, from: &Keypair, amount: &i128) {
         let from_id = to_ed25519(&self.env, from);
         let nonce = self.nonce(&from_id);
@@ -195,6 +268,27 @@ impl Token {
         TokenClient::new(&self.env, &self.contract_id).set_auth(&auth, &nonce, id, &authorize)
     }
 
+    pub fn auth_batch(&self, admin: &Keypair, ids: &Vec<Identifier>) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("auth_batch"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce, ids).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).auth_batch(&auth, &nonce, ids)
+    }
+
+    pub fn auth_flags(&self) -> u32 {
+        TokenClient::new(&self.env, &self.contract_id).auth_flags()
+    }
+
     pub fn mint(&self, admin: &Keypair, to: &Identifier, amount: &i128) {
         let admin_id = to_ed25519(&self.env, admin);
         let nonce = self.nonce(&admin_id);
@@ -229,6 +323,44 @@ impl Token {
         TokenClient::new(&self.env, &self.contract_id).set_admin(&auth, &nonce, new_admin)
     }
 
+    pub fn accept_adm(&self, new_admin: &Keypair) {
+        let new_admin_id = to_ed25519(&self.env, new_admin);
+        let nonce = self.nonce(&new_admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("accept_adm"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (new_admin_id, &nonce).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: new_admin.public.to_bytes().into_val(&self.env),
+            signature: new_admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).accept_adm(&auth, &nonce)
+    }
+
+    pub fn cancel_adm(&self, admin: &Keypair) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("cancel_adm"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).cancel_adm(&auth, &nonce)
+    }
+
+    pub fn nominee(&self) -> Option<Identifier> {
+        TokenClient::new(&self.env, &self.contract_id).nominee()
+    }
+
     pub fn decimals(&self) -> u32 {
         TokenClient::new(&self.env, &self.contract_id).decimals()
     }
@@ -240,4 +372,243 @@ impl Token {
     pub fn symbol(&self) -> Bytes {
         TokenClient::new(&self.env, &self.contract_id).symbol()
     }
+
+    pub fn set_info(&self, admin: &Keypair, info: &TokenInfo) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("set_info"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce, info).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).set_info(&auth, &nonce, info)
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        TokenClient::new(&self.env, &self.contract_id).metadata()
+    }
+
+    pub fn grant_vest(
+        &self,
+        admin: &Keypair,
+        beneficiary: &Identifier,
+        total: &i128,
+        start: u32,
+        cliff: u32,
+        duration: u32,
+    ) -> u32 {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("grant_vest"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce, beneficiary, total, start, cliff, duration)
+                .into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).grant_vest(
+            &auth,
+            &nonce,
+            beneficiary,
+            total,
+            &start,
+            &cliff,
+            &duration,
+        )
+    }
+
+    pub fn claim_vest(&self, beneficiary: &Identifier) -> i128 {
+        TokenClient::new(&self.env, &self.contract_id).claim_vest(beneficiary)
+    }
+
+    pub fn vesting_of(&self, grant_id: u32) -> Option<VestingGrant> {
+        TokenClient::new(&self.env, &self.contract_id).vesting_of(&grant_id)
+    }
+
+    pub fn grants_of(&self, id: &Identifier) -> Vec<u32> {
+        TokenClient::new(&self.env, &self.contract_id).grants_of(id)
+    }
+
+    pub fn add_role(&self, admin: &Keypair, role: Role, id: &Identifier) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("add_role"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce, role, id).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).add_role(&auth, &nonce, &role, id)
+    }
+
+    pub fn del_role(&self, admin: &Keypair, role: Role, id: &Identifier) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("del_role"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce, role, id).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).del_role(&auth, &nonce, &role, id)
+    }
+
+    pub fn has_role(&self, role: Role, id: &Identifier) -> bool {
+        TokenClient::new(&self.env, &self.contract_id).has_role(&role, id)
+    }
+
+    pub fn members(&self, role: Role) -> Vec<Identifier> {
+        TokenClient::new(&self.env, &self.contract_id).members(&role)
+    }
+
+    pub fn pause(&self, admin: &Keypair) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("pause"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).pause(&auth, &nonce)
+    }
+
+    pub fn unpause(&self, admin: &Keypair) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("unpause"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).unpause(&auth, &nonce)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        TokenClient::new(&self.env, &self.contract_id).is_paused()
+    }
+
+    pub fn holders(&self, start: u32, limit: u32) -> Vec<Identifier> {
+        TokenClient::new(&self.env, &self.contract_id).holders(&start, &limit)
+    }
+
+    pub fn holder_cnt(&self) -> u32 {
+        TokenClient::new(&self.env, &self.contract_id).holder_cnt()
+    }
+
+    pub fn set_fee(&self, admin: &Keypair, fee_bps: u32, treasury: &Identifier) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("set_fee"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce, fee_bps, treasury).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).set_fee(&auth, &nonce, &fee_bps, treasury)
+    }
+
+    pub fn set_exempt(&self, admin: &Keypair, id: &Identifier, exempt: bool) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("set_exempt"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce, id, exempt).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).set_exempt(&auth, &nonce, id, &exempt)
+    }
+
+    pub fn reindex(&self, admin: &Keypair, ids: &Vec<Identifier>) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("reindex"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce, ids).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).reindex(&auth, &nonce, ids)
+    }
+
+    pub fn fee_bps(&self) -> u32 {
+        TokenClient::new(&self.env, &self.contract_id).fee_bps()
+    }
+
+    pub fn treasury(&self) -> Option<Identifier> {
+        TokenClient::new(&self.env, &self.contract_id).treasury()
+    }
+
+    pub fn is_exempt(&self, id: &Identifier) -> bool {
+        TokenClient::new(&self.env, &self.contract_id).is_exempt(id)
+    }
+
+    pub fn migrate(&self, admin: &Keypair) {
+        let admin_id = to_ed25519(&self.env, admin);
+        let nonce = self.nonce(&admin_id);
+
+        let msg = SignaturePayload::V0(SignaturePayloadV0 {
+            name: symbol!("migrate"),
+            contract: self.contract_id.clone(),
+            network: self.env.ledger().network_passphrase(),
+            args: (admin_id, &nonce).into_val(&self.env),
+        });
+        let auth = Signature::Ed25519(Ed25519Signature {
+            public_key: admin.public.to_bytes().into_val(&self.env),
+            signature: admin.sign(msg).unwrap().into_val(&self.env),
+        });
+        TokenClient::new(&self.env, &self.contract_id).migrate(&auth, &nonce)
+    }
+
+    pub fn version(&self) -> u32 {
+        TokenClient::new(&self.env, &self.contract_id).version()
+    }
 }
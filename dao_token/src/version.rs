@@ -0,0 +1,64 @@
+use crate::admin::{read_v0_administrator, remove_v0_administrator, write_administrator};
+use crate::balance::AUTH_REVOCABLE;
+use crate::storage_types::{AllowanceDataKey, DataKey};
+use soroban_sdk::Env;
+
+// Bump together with a new step in `migrate` whenever stored data changes
+// shape. There's no `upgrade` on this SDK, see the README.
+pub const CONTRACT_VERSION: u32 = 2;
+
+// Contracts deployed before the version key existed read as version 0.
+pub fn read_version(e: &Env) -> u32 {
+    let key = DataKey::Version;
+    e.storage().get(key).unwrap_or(Ok(0)).unwrap()
+}
+
+pub fn write_version(e: &Env, version: u32) {
+    let key = DataKey::Version;
+    e.storage().set(key, version);
+}
+
+// Runs every step between the stored version and CONTRACT_VERSION, once.
+// Returns the version the contract was at before migrating.
+pub fn migrate(e: &Env) -> u32 {
+    let from = read_version(e);
+    if from >= CONTRACT_VERSION {
+        return from;
+    }
+    for version in from..CONTRACT_VERSION {
+        match version {
+            0 => migrate_v0(e),
+            // Allowances can't be enumerated, they move on their next write.
+            // Until then reads fall back to the old key.
+            1 => {}
+            _ => unreachable!(),
+        }
+    }
+    write_version(e, CONTRACT_VERSION);
+    from
+}
+
+// v0 contracts predate auth flags and could always revoke authorization.
+// Their single administrator gets every role.
+fn migrate_v0(e: &Env) {
+    if let Some(admin) = read_v0_administrator(e) {
+        write_administrator(e, admin);
+        remove_v0_administrator(e);
+    }
+
+    let key = DataKey::AuthFlags;
+    if !e.storage().has(key.clone()) {
+        e.storage().set(key, AUTH_REVOCABLE);
+    }
+}
+
+// The amount of a v0/v1 allowance entry, if there is one.
+pub fn read_v1_allowance(e: &Env, key: &AllowanceDataKey) -> Option<i128> {
+    let key = DataKey::Allowance(key.clone());
+    e.storage().get(key).map(|amount| amount.unwrap())
+}
+
+pub fn remove_v1_allowance(e: &Env, key: &AllowanceDataKey) {
+    let key = DataKey::Allowance(key.clone());
+    e.storage().remove(key);
+}
@@ -0,0 +1,66 @@
+use crate::error::Error;
+use crate::storage_types::{DataKey, VestingGrant};
+use soroban_auth::Identifier;
+use soroban_sdk::{panic_with_error, Env, Vec};
+
+// Grants are numbered in the order they are made. A beneficiary can hold
+// any number of them, each with its own schedule, and `Grants` lists the
+// ids of theirs that aren't fully claimed yet.
+
+pub fn read_vesting(e: &Env, grant_id: u32) -> Option<VestingGrant> {
+    let key = DataKey::Vesting(grant_id);
+    e.storage().get(key).map(|grant| grant.unwrap())
+}
+
+pub fn write_vesting(e: &Env, grant_id: u32, grant: VestingGrant) {
+    let key = DataKey::Vesting(grant_id);
+    e.storage().set(key, grant);
+}
+
+pub fn read_grants(e: &Env, beneficiary: Identifier) -> Vec<u32> {
+    let key = DataKey::Grants(beneficiary);
+    e.storage()
+        .get(key)
+        .unwrap_or_else(|| Ok(Vec::new(e)))
+        .unwrap()
+}
+
+pub fn write_grants(e: &Env, beneficiary: Identifier, grants: Vec<u32>) {
+    let key = DataKey::Grants(beneficiary);
+    e.storage().set(key, grants);
+}
+
+// Stores a new grant and returns its id.
+pub fn add_grant(e: &Env, grant: VestingGrant) -> u32 {
+    let grant_id: u32 = e.storage().get(DataKey::VestCnt).unwrap_or(Ok(0)).unwrap();
+    let next = grant_id
+        .checked_add(1)
+        .unwrap_or_else(|| panic_with_error!(e, Error::Overflow));
+
+    let mut grants = read_grants(e, grant.recipient.clone());
+    grants.push_back(grant_id);
+    write_grants(e, grant.recipient.clone(), grants);
+    write_vesting(e, grant_id, grant);
+    e.storage().set(DataKey::VestCnt, next);
+    grant_id
+}
+
+// Linear vesting from `start`, with nothing released before `start + cliff`
+// and everything released from `start + duration` onwards.
+pub fn vested_amount(e: &Env, grant: &VestingGrant) -> i128 {
+    let now = e.ledger().sequence();
+    if now < grant.start.saturating_add(grant.cliff) {
+        return 0;
+    }
+
+    let elapsed = now - grant.start;
+    if elapsed >= grant.duration {
+        return grant.total;
+    }
+
+    grant
+        .total
+        .checked_mul(i128::from(elapsed))
+        .unwrap_or_else(|| panic_with_error!(e, Error::Overflow))
+        / i128::from(grant.duration)
+}
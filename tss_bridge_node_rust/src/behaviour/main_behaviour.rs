@@ -2,7 +2,8 @@ use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::time::Duration;
 
-use libp2p::gossipsub::{Gossipsub, self, ValidationMode, MessageId, GossipsubMessage, MessageAuthenticity, GossipsubEvent};
+use libp2p::gossipsub::{Gossipsub, self, ValidationMode, MessageId, GossipsubMessage, MessageAuthenticity, GossipsubEvent,
+    IdentTopic, PeerScoreParams, PeerScoreThresholds, TopicScoreParams};
 use libp2p::{identify, autonat, identity, PeerId};
 use libp2p::kad::{Kademlia, KademliaEvent};
 use libp2p::kad::store::MemoryStore;
@@ -10,6 +11,8 @@ use libp2p::swarm::behaviour::toggle::Toggle;
 use libp2p::{swarm::NetworkBehaviour};
 use libp2p::relay::v2::relay;
 
+use super::tss_behaviour::{TssBehaviour, TssEvent};
+
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "Event", event_process = false)]
 pub struct Behaviour {
@@ -17,15 +20,19 @@ pub struct Behaviour {
     identify: identify::Behaviour,
     pub kademelia: Kademlia<MemoryStore>,
     pub gossipsub: Gossipsub,
+    pub tss: TssBehaviour,
 }
 
 impl Behaviour {
     pub fn new(key: identity::Keypair,pub_key: identity::PublicKey) -> Self {
+        let local_peer_id = PeerId::from_public_key(&pub_key);
         let mut kademelia = Kademlia::new(PeerId::from_public_key(&pub_key),MemoryStore::new(PeerId::from_public_key(&pub_key),));
 
 
         let message_id_fn = |message: &GossipsubMessage| {
             let mut s = DefaultHasher::new();
+            // the same message from two members isn't a duplicate
+            message.source.hash(&mut s);
             message.data.hash(&mut s);
             MessageId::from(s.finish().to_string())
         };
@@ -34,7 +41,8 @@ impl Behaviour {
         let gossipsub_config = gossipsub::GossipsubConfigBuilder::default()
         .duplicate_cache_time(Duration::from_micros(0))
         .heartbeat_interval(Duration::from_secs(10)) // This is set to aid debugging by not cluttering the log space
-        .validation_mode(ValidationMode::Permissive) // This sets the kind of message validation. The default is Strict (enforce message signing)
+        .validation_mode(ValidationMode::Strict) // every message has to be signed by its source
+        .validate_messages() // nothing is forwarded before the node checked the envelope
         .message_id_fn(message_id_fn) // content-address messages. No two messages of the same content will be propagated.
         .build()
         .expect("Valid config");
@@ -42,6 +50,9 @@ impl Behaviour {
     // build a gossipsub network behaviour
     let mut gossipsub = Gossipsub::new(MessageAuthenticity::Signed(key), gossipsub_config)
         .expect("Correct configuration");
+    // peers that send messages the node rejects lose score, see `subscribe`
+    gossipsub.with_peer_score(PeerScoreParams::default(), PeerScoreThresholds::default())
+        .expect("Valid peer score parameters");
 
         Self {
             relay: relay::Relay::new(PeerId::from(pub_key.clone()), Default::default()),
@@ -51,9 +62,30 @@ impl Behaviour {
                 ),
             ),
             kademelia,
-            gossipsub
+            gossipsub,
+            tss: TssBehaviour::new(local_peer_id),
         }
     }
+
+    // Subscribes to `topic` with invalid messages counting against the peers
+    // that deliver them. Two of those put a peer below the graylist
+    // threshold and gossipsub ignores it until the penalty decayed.
+    pub fn subscribe(&mut self, topic: &IdentTopic) {
+        let params = TopicScoreParams {
+            topic_weight: 1.0,
+            // the rest is for busier topics than ours, only count invalid messages
+            time_in_mesh_weight: 0.0,
+            first_message_deliveries_weight: 0.0,
+            mesh_message_deliveries_weight: 0.0,
+            mesh_failure_penalty_weight: 0.0,
+            invalid_message_deliveries_weight: -20.0,
+            // roughly halves every minute
+            invalid_message_deliveries_decay: 0.99,
+            ..Default::default()
+        };
+        self.gossipsub.set_topic_params(topic.clone(), params).expect("peer scoring is on");
+        self.gossipsub.subscribe(topic).unwrap();
+    }
 }
 
 
@@ -64,7 +96,8 @@ pub enum Event {
     Relay(relay::Event),
     Kademlia(KademliaEvent),
     Autonat(autonat::Event),
-    Gossipsub(GossipsubEvent)
+    Gossipsub(GossipsubEvent),
+    Tss(TssEvent),
 }
 
 impl From<identify::Event> for Event {
@@ -96,3 +129,9 @@ impl From<GossipsubEvent> for Event {
         Event::Gossipsub(value)
     }
 }
+
+impl From<TssEvent> for Event {
+    fn from(event: TssEvent) -> Self {
+        Event::Tss(event)
+    }
+}
@@ -1,2 +1,3 @@
 pub mod main_behaviour;
-mod tss_behaviour;
\ No newline at end of file
+pub mod tss_behaviour;
+mod tss_handler;
\ No newline at end of file
@@ -1,30 +1,714 @@
-use dkg::ThresholdKeys;
-use libp2p::swarm::NetworkBehaviour;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{FutureExt, StreamExt};
+use libp2p::core::connection::ConnectionId;
+use libp2p::swarm::behaviour::{ConnectionClosed, ConnectionEstablished, DialFailure, FromSwarm};
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p::swarm::{NetworkBehaviour, NetworkBehaviourAction, NotifyHandler, PollParameters};
+use libp2p::PeerId;
+use log::{info, warn};
 use modular_frost::curve::Ed25519;
+use modular_frost::ThresholdKeys;
+
+use super::tss_handler::{HandlerEvent, Outgoing, SessionMessage, TssHandler};
+use crate::signing::{choose_signers, is_signing_set, verify_signature, SignError, SignSession, SignThreadOutput};
+use crate::tss::{KeyGenSession, KeyGenThreadOutput};
+
+// how often undelivered messages are tried again
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+// how often a message is tried before we give up on its peer
+const MAX_ATTEMPTS: u32 = 10;
+// sign requests from other members waiting for approval or for their
+// signature at once, more are dropped until some are done
+const MAX_PENDING_REQUESTS: usize = 16;
+// the requester's session has timed out by then
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+// DKG messages kept for a key generation we haven't started yet
+const MAX_EARLY_MESSAGES: usize = 64;
+// requests we keep early signing messages for, each holds at most a
+// preprocess and a share from every other member
+const MAX_EARLY_SIGN_REQUESTS: usize = 16;
+// finished requests remembered to drop their late messages
+const MAX_FINISHED_SIGNS: usize = 1024;
+
+// A finished key generation, everything needed to sign with it.
+pub struct Config {
+    pub session: u64,
+    // indexed by participant index - 1
+    pub members: Vec<PeerId>,
+    pub keys: ThresholdKeys<Ed25519>,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Sign(SignError),
+    // a member didn't acknowledge our messages in time
+    Unreachable(PeerId),
+}
+
+#[derive(Debug)]
+pub enum TssEvent {
+    KeyGenerated { session: u64, group_key: [u8; 32] },
+    // `faulty` are the members an accusation proved wrong, `unresponsive`
+    // the ones we timed out on. Both are empty if there's nobody to blame.
+    KeyGenFailed { session: u64, faulty: Vec<PeerId>, unresponsive: Vec<PeerId> },
+    // another member wants us to co-sign, answer with `approve_sign` or
+    // `reject_sign`
+    SignRequested { request: u64, from: PeerId, message: Vec<u8> },
+    SignatureProduced { request: u64, signature: [u8; 64] },
+    // `id` is the DKG session or the signing request that failed
+    SessionFailed { id: u64, error: SessionError },
+}
+
+struct KeyGenRun {
+    session: u64,
+    members: Vec<PeerId>,
+    key_gen: KeyGenSession,
+    // per run, so a cancelled run can't talk into the next one
+    output: UnboundedReceiver<KeyGenThreadOutput>,
+}
+
+// Signing messages for a request we don't know yet.
+struct EarlySignMessages {
+    messages: Vec<(PeerId, SessionMessage)>,
+    since: Instant,
+}
 
+// The most recent `capacity` ids, oldest forgotten first.
+struct RecentIds {
+    order: VecDeque<u64>,
+    ids: HashSet<u64>,
+    capacity: usize,
+}
+
+impl RecentIds {
+    fn new(capacity: usize) -> Self {
+        RecentIds { order: VecDeque::new(), ids: HashSet::new(), capacity }
+    }
 
+    fn contains(&self, id: &u64) -> bool {
+        self.ids.contains(id)
+    }
 
-pub struct Config{
-    keys: ThresholdKeys<Ed25519>
+    // Whether `id` is new.
+    fn insert(&mut self, id: u64) -> bool {
+        if !self.ids.insert(id) {
+            return false;
+        }
+        self.order.push_back(id);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
 }
-pub struct TssBehaviour{
-    config: Config
+
+// A sign request from another member.
+struct PendingRequest {
+    message: Vec<u8>,
+    signers: Vec<u16>,
+    since: Instant,
 }
 
-// impl NetworkBehaviour for TssBehaviour{
-//     type ConnectionHandler;
+// Runs the DKG and the signing sessions of this node. Session members talk
+// point to point over the tss protocol, every message is acknowledged and
+// sent again until it is.
+pub struct TssBehaviour {
+    local_peer_id: PeerId,
+    config: Option<Config>,
+    key_gen: Option<KeyGenRun>,
+    // the key generation the members agreed on but we haven't started
+    expected_key_gen: Option<(u64, Vec<PeerId>)>,
+    // its messages that arrived before we started it
+    early_messages: Vec<(PeerId, SessionMessage)>,
+    sign_sessions: HashMap<u64, SignSession>,
+    // requests from other members we haven't decided on
+    awaiting_approval: HashMap<u64, PendingRequest>,
+    // requests we aren't a signer of, kept to check the signature
+    awaiting_signature: HashMap<u64, PendingRequest>,
+    // signing messages that arrived before their request
+    early_sign_messages: HashMap<u64, EarlySignMessages>,
+    // requests we're done with, their messages can still show up
+    finished_signs: RecentIds,
+    sign_sender: UnboundedSender<SignThreadOutput>,
+    sign_output: UnboundedReceiver<SignThreadOutput>,
+    connected: HashSet<PeerId>,
+    dialing: HashSet<PeerId>,
+    // messages for peers we have no connection to yet
+    waiting: HashMap<PeerId, Vec<Outgoing>>,
+    next_message_id: u64,
+    retry_timer: Pin<Box<dyn Future<Output = ()> + Send>>,
+    events: VecDeque<NetworkBehaviourAction<TssEvent, TssHandler>>,
+}
+
+impl TssBehaviour {
+    pub fn new(local_peer_id: PeerId) -> Self {
+        let (sign_sender, sign_output) = mpsc::unbounded();
+        TssBehaviour {
+            local_peer_id,
+            config: None,
+            key_gen: None,
+            expected_key_gen: None,
+            early_messages: Vec::new(),
+            sign_sessions: HashMap::new(),
+            awaiting_approval: HashMap::new(),
+            awaiting_signature: HashMap::new(),
+            early_sign_messages: HashMap::new(),
+            finished_signs: RecentIds::new(MAX_FINISHED_SIGNS),
+            sign_sender,
+            sign_output,
+            connected: HashSet::new(),
+            dialing: HashSet::new(),
+            waiting: HashMap::new(),
+            next_message_id: 0,
+            retry_timer: Box::pin(async_std::task::sleep(RETRY_INTERVAL)),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn config(&self) -> Option<&Config> {
+        self.config.as_ref()
+    }
+
+    // Picks up a key generated in an earlier run.
+    pub fn restore(&mut self, config: Config) {
+        self.config = Some(config);
+    }
+
+    // The members agreed on a list, its DKG messages can arrive before
+    // everyone accepted it and we start.
+    pub fn expect_key_gen(&mut self, session: u64, members: Vec<PeerId>) {
+        self.expected_key_gen = Some((session, members));
+        self.early_messages.clear();
+    }
+
+    // `members` are in participant index order and include us.
+    pub fn start_key_gen(&mut self, session: u64, members: Vec<PeerId>, threshold: u16, context: String) {
+        let Some(position) = members.iter().position(|peer| *peer == self.local_peer_id) else {
+            return;
+        };
+        let n = members.len() as u16;
+        let index = position as u16 + 1;
+        info!("starting key generation as {} of {}", index, n);
+        let (sender, output) = mpsc::unbounded();
+        self.expected_key_gen = None;
+        let key_gen = match KeyGenSession::start(context, threshold, n, index, sender) {
+            Ok(key_gen) => key_gen,
+            Err(error) => {
+                warn!("can't start key generation for session {}: {}", session, error);
+                self.early_messages.clear();
+                let (faulty, unresponsive) = (Vec::new(), Vec::new());
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(TssEvent::KeyGenFailed { session, faulty, unresponsive }));
+                return;
+            },
+        };
+        self.key_gen = Some(KeyGenRun { session, members, key_gen, output });
+        for (peer, message) in std::mem::take(&mut self.early_messages) {
+            self.on_message(peer, message);
+        }
+    }
+
+    // Gives up on the running key generation, its thread exits once it
+    // notices. Messages still on their way for it are dropped by the others.
+    pub fn cancel_key_gen(&mut self) {
+        if let Some(run) = self.key_gen.take() {
+            info!("cancelled key generation for session {}", run.session);
+        }
+        self.expected_key_gen = None;
+        self.early_messages.clear();
+    }
+
+    // Asks the session to sign `message` with signers picked from the
+    // `alive` members, the result comes back as an event for the returned
+    // request id.
+    pub fn sign(&mut self, message: Vec<u8>, alive: &[PeerId]) -> Result<u64, SignError> {
+        let config = self.config.as_ref().ok_or(SignError::NoKeys)?;
+        let indices = config.members.iter().enumerate()
+            .filter(|(_, peer)| alive.contains(peer))
+            .map(|(i, _)| i as u16 + 1)
+            .collect();
+        let params = config.keys.params();
+        let signers = choose_signers(indices, params.i(), params.t()).ok_or(SignError::InvalidSigningSet)?;
+        let request = rand::random();
+        self.start_signing(request, message.clone(), signers.clone())?;
+        for peer in self.other_members() {
+            self.send(peer, SessionMessage::SignRequest { request, message: message.clone(), signers: signers.clone() });
+        }
+        Ok(request)
+    }
+
+    // The requests from other members for `message` that wait for approval.
+    pub fn awaiting_approval(&self, message: &[u8]) -> Vec<u64> {
+        self.awaiting_approval.iter()
+            .filter(|(_, pending)| pending.message == message)
+            .map(|(request, _)| *request)
+            .collect()
+    }
+
+    // Co-signs a request from another member.
+    pub fn approve_sign(&mut self, request: u64) {
+        let Some(pending) = self.awaiting_approval.remove(&request) else {
+            return;
+        };
+        if let Err(e) = self.start_signing(request, pending.message, pending.signers) {
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(TssEvent::SessionFailed {
+                id: request,
+                error: SessionError::Sign(e),
+            }));
+        }
+    }
 
-//     type OutEvent;
+    // Stays out of a request from another member. Without us it can only
+    // time out.
+    pub fn reject_sign(&mut self, request: u64) {
+        if self.awaiting_approval.remove(&request).is_some() {
+            self.early_sign_messages.remove(&request);
+            self.finished_signs.insert(request);
+        }
+    }
 
-//     fn new_handler(&mut self) -> Self::ConnectionHandler {
-//         todo!()
-//     }
+    fn other_members(&self) -> Vec<PeerId> {
+        let members = match (&self.key_gen, &self.config) {
+            (Some(run), _) => &run.members,
+            (None, Some(config)) => &config.members,
+            (None, None) => return Vec::new(),
+        };
+        members.iter().filter(|peer| **peer != self.local_peer_id).cloned().collect()
+    }
 
-//     fn poll(
-//         &mut self,
-//         cx: &mut std::task::Context<'_>,
-//         params: &mut impl libp2p::swarm::PollParameters,
-//     ) -> std::task::Poll<libp2p::swarm::NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
-//         todo!()
-//     }
-// }
\ No newline at end of file
+    // Whether `peer` is the member holding participant index `from`.
+    fn is_participant(members: &[PeerId], peer: &PeerId, from: u16) -> bool {
+        (from as usize).checked_sub(1).and_then(|i| members.get(i)) == Some(peer)
+    }
+
+    fn start_signing(&mut self, request: u64, message: Vec<u8>, signers: Vec<u16>) -> Result<(), SignError> {
+        let config = self.config.as_ref().ok_or(SignError::NoKeys)?;
+        let session = SignSession::start(request, config.keys.clone(), signers, message, self.sign_sender.clone());
+        self.sign_sessions.insert(request, session);
+        let early = self.early_sign_messages.remove(&request).map_or_else(Vec::new, |early| early.messages);
+        for (peer, message) in early {
+            self.on_message(peer, message);
+        }
+        Ok(())
+    }
+
+    fn on_message(&mut self, peer: PeerId, message: SessionMessage) {
+        match message {
+            SessionMessage::Commitments { session, from, .. }
+            | SessionMessage::SecretShare { session, from, .. }
+            | SessionMessage::KeyGenConfirmation { session, from }
+            | SessionMessage::Accusation { session, from, .. } => {
+                let Some(run) = self.key_gen.as_ref() else {
+                    let expected = self.expected_key_gen.as_ref()
+                        .map_or(false, |(id, members)| *id == session && Self::is_participant(members, &peer, from));
+                    if expected && self.early_messages.len() < MAX_EARLY_MESSAGES {
+                        self.early_messages.push((peer, message));
+                    }
+                    return;
+                };
+                if run.session != session || !Self::is_participant(&run.members, &peer, from) {
+                    warn!("dropping key generation message {} from {:?}", from, peer);
+                    return;
+                }
+                let result = match message {
+                    SessionMessage::Commitments { data, .. } => run.key_gen.handle_commitments(from, &data),
+                    SessionMessage::SecretShare { data, .. } => run.key_gen.handle_share(from, &data),
+                    // blame needs a participant other than the accuser
+                    SessionMessage::Accusation { accused, .. } if accused == from || !(1..=run.members.len()).contains(&(accused as usize)) => {
+                        warn!("dropping accusation of participant {} from {:?}", accused, peer);
+                        Ok(())
+                    },
+                    SessionMessage::Accusation { accused, share, proof, .. } => {
+                        warn!("{:?} accuses participant {} of sending an invalid share", peer, accused);
+                        run.key_gen.handle_accusation(from, accused, &share, proof.as_deref())
+                    },
+                    _ => {
+                        run.key_gen.handle_confirmation(from);
+                        Ok(())
+                    },
+                };
+                if let Err(e) = result {
+                    warn!("invalid key generation message from {}: {:?}", from, e);
+                }
+            },
+            SessionMessage::SignRequest { request, message, signers } => {
+                let Some(config) = self.config.as_ref() else {
+                    return;
+                };
+                let known = self.sign_sessions.contains_key(&request)
+                    || self.awaiting_approval.contains_key(&request)
+                    || self.awaiting_signature.contains_key(&request)
+                    || self.finished_signs.contains(&request);
+                if !config.members.contains(&peer) || known {
+                    return;
+                }
+                let params = config.keys.params();
+                let requester = config.members.iter().position(|member| *member == peer).map_or(0, |i| i as u16 + 1);
+                if !is_signing_set(&signers, requester, params.t(), params.n()) {
+                    warn!("dropping sign request {} from {:?} with signers {:?}", request, peer, signers);
+                    return;
+                }
+                // members outside the signing set just wait for the signature
+                let is_signer = signers.contains(&params.i());
+                let pending = if is_signer { &mut self.awaiting_approval } else { &mut self.awaiting_signature };
+                if pending.len() >= MAX_PENDING_REQUESTS {
+                    warn!("too many pending sign requests, dropping {} from {:?}", request, peer);
+                    return;
+                }
+                pending.insert(request, PendingRequest { message: message.clone(), signers, since: Instant::now() });
+                if is_signer {
+                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(TssEvent::SignRequested { request, from: peer, message }));
+                }
+            },
+            SessionMessage::SignPreprocess { request, from, .. } | SessionMessage::SignShare { request, from, .. } => {
+                let Some(config) = self.config.as_ref() else {
+                    return;
+                };
+                if self.finished_signs.contains(&request) || !Self::is_participant(&config.members, &peer, from) {
+                    return;
+                }
+                let Some(session) = self.sign_sessions.get(&request) else {
+                    let per_request = 2 * config.members.len();
+                    if !self.early_sign_messages.contains_key(&request) && self.early_sign_messages.len() >= MAX_EARLY_SIGN_REQUESTS {
+                        warn!("too many early signing messages, dropping one for {} from {:?}", request, peer);
+                        return;
+                    }
+                    let early = self.early_sign_messages.entry(request)
+                        .or_insert_with(|| EarlySignMessages { messages: Vec::new(), since: Instant::now() });
+                    if early.messages.len() < per_request {
+                        early.messages.push((peer, message));
+                    }
+                    return;
+                };
+                match message {
+                    SessionMessage::SignPreprocess { data, .. } => session.handle_preprocess(from, data),
+                    SessionMessage::SignShare { data, .. } => session.handle_share(from, data),
+                    _ => {}
+                }
+            },
+            SessionMessage::Signature { request, signature } => {
+                let Some(config) = self.config.as_ref() else {
+                    return;
+                };
+                let Ok(signature) = <[u8; 64]>::try_from(signature) else {
+                    return;
+                };
+                let message = self.sign_sessions.get(&request).map(|session| &session.message)
+                    .or_else(|| self.awaiting_approval.get(&request).map(|pending| &pending.message))
+                    .or_else(|| self.awaiting_signature.get(&request).map(|pending| &pending.message));
+                let Some(message) = message else {
+                    return;
+                };
+                if !config.members.contains(&peer) || !verify_signature(&config.keys, message, &signature) {
+                    warn!("dropping an invalid signature for request {} from {:?}", request, peer);
+                    return;
+                }
+                self.sign_sessions.remove(&request);
+                self.awaiting_approval.remove(&request);
+                self.awaiting_signature.remove(&request);
+                self.early_sign_messages.remove(&request);
+                if self.finished_signs.insert(request) {
+                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(TssEvent::SignatureProduced { request, signature }));
+                }
+            },
+        }
+    }
+
+    fn on_key_gen_output(&mut self, output: KeyGenThreadOutput) {
+        let Some(run) = self.key_gen.as_ref() else {
+            return;
+        };
+        let (session, index) = (run.session, run.key_gen.params.i());
+        match output {
+            KeyGenThreadOutput::Commitments(commitments) => {
+                let data = commitments.serialize();
+                for peer in self.other_members() {
+                    self.send(peer, SessionMessage::Commitments { session, from: index, data: data.clone() });
+                }
+            },
+            KeyGenThreadOutput::SecretShares(shares) => {
+                let recipients: Vec<(PeerId, Vec<u8>)> = shares.into_iter()
+                    .filter_map(|(i, share)| Some((*run.members.get(i as usize - 1)?, share.serialize())))
+                    .collect();
+                for (peer, data) in recipients {
+                    self.send(peer, SessionMessage::SecretShare { session, from: index, data });
+                }
+            },
+            KeyGenThreadOutput::Key(core) => {
+                let run = self.key_gen.take().unwrap();
+                let keys = ThresholdKeys::new(core);
+                let group_key = keys.group_key().compress().to_bytes();
+                self.config = Some(Config { session, members: run.members, keys });
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(TssEvent::KeyGenerated { session, group_key }));
+            },
+            KeyGenThreadOutput::Confirmation => {
+                for peer in self.other_members() {
+                    self.send(peer, SessionMessage::KeyGenConfirmation { session, from: index });
+                }
+            },
+            KeyGenThreadOutput::Accusation { participant, share, proof } => {
+                warn!("participant {} sent us an invalid share, accusing them", participant);
+                for peer in self.other_members() {
+                    let (share, proof) = (share.clone(), proof.clone());
+                    self.send(peer, SessionMessage::Accusation { session, from: index, accused: participant, share, proof });
+                }
+            },
+            KeyGenThreadOutput::Failed(error) => {
+                let run = self.key_gen.take().unwrap();
+                self.early_messages.clear();
+                warn!("key generation for session {} failed: {}", session, error);
+                let faulty = Self::members_at(&run.members, error.faulty_participants());
+                let unresponsive = Self::members_at(&run.members, error.unresponsive_participants());
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(TssEvent::KeyGenFailed { session, faulty, unresponsive }));
+            },
+        }
+    }
+
+    fn members_at(members: &[PeerId], indices: &[u16]) -> Vec<PeerId> {
+        indices.iter()
+            .filter_map(|i| (*i as usize).checked_sub(1).and_then(|i| members.get(i)).cloned())
+            .collect()
+    }
+
+    fn on_sign_output(&mut self, output: SignThreadOutput) {
+        let Some(config) = self.config.as_ref() else {
+            return;
+        };
+        let index = config.keys.params().i();
+        match output {
+            SignThreadOutput::Preprocess(request, ref data) | SignThreadOutput::Share(request, ref data) => {
+                let Some(session) = self.sign_sessions.get(&request) else {
+                    return;
+                };
+                let signers: Vec<PeerId> = session.signers.iter()
+                    .filter(|i| **i != index)
+                    .filter_map(|i| config.members.get(*i as usize - 1).cloned())
+                    .collect();
+                for peer in signers {
+                    let message = match output {
+                        SignThreadOutput::Preprocess(..) => SessionMessage::SignPreprocess { request, from: index, data: data.clone() },
+                        _ => SessionMessage::SignShare { request, from: index, data: data.clone() },
+                    };
+                    self.send(peer, message);
+                }
+            },
+            SignThreadOutput::Signature(request, signature) => {
+                let Some(session) = self.sign_sessions.remove(&request) else {
+                    return;
+                };
+                // the members outside the signing set get it from us
+                let others: Vec<PeerId> = config.members.iter().enumerate()
+                    .filter(|(i, _)| !session.signers.contains(&(*i as u16 + 1)))
+                    .map(|(_, peer)| *peer)
+                    .collect();
+                for peer in others {
+                    self.send(peer, SessionMessage::Signature { request, signature: signature.to_vec() });
+                }
+                self.finished_signs.insert(request);
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(TssEvent::SignatureProduced { request, signature }));
+            },
+            SignThreadOutput::Failed(request, error) => {
+                self.finished_signs.insert(request);
+                // sessions we dropped ourselves aren't worth an event
+                if self.sign_sessions.remove(&request).is_some() {
+                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(TssEvent::SessionFailed {
+                        id: request,
+                        error: SessionError::Sign(error),
+                    }));
+                }
+            },
+        }
+    }
+
+    fn send(&mut self, peer: PeerId, message: SessionMessage) {
+        let outgoing = Outgoing { id: self.next_message_id, message, attempts: 0 };
+        self.next_message_id += 1;
+        self.dispatch(peer, outgoing);
+    }
+
+    fn dispatch(&mut self, peer: PeerId, outgoing: Outgoing) {
+        if self.connected.contains(&peer) {
+            self.events.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: peer,
+                handler: NotifyHandler::Any,
+                event: outgoing,
+            });
+        } else {
+            self.waiting.entry(peer).or_default().push(outgoing);
+            if self.dialing.insert(peer) {
+                self.events.push_back(NetworkBehaviourAction::Dial {
+                    opts: DialOpts::peer_id(peer).condition(PeerCondition::Disconnected).build(),
+                    handler: TssHandler::new(),
+                });
+            }
+        }
+    }
+
+    // Counts a failed attempt, the message waits for the next retry.
+    fn retry_later(&mut self, peer: PeerId, mut outgoing: Outgoing) {
+        outgoing.attempts += 1;
+        if outgoing.attempts < MAX_ATTEMPTS {
+            self.waiting.entry(peer).or_default().push(outgoing);
+        } else {
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(TssEvent::SessionFailed {
+                id: outgoing.message.id(),
+                error: SessionError::Unreachable(peer),
+            }));
+        }
+    }
+
+    fn expire_requests(&mut self) {
+        let expired: Vec<u64> = self.awaiting_approval.iter()
+            .filter(|(_, pending)| pending.since.elapsed() > REQUEST_TIMEOUT)
+            .map(|(request, _)| *request)
+            .collect();
+        for request in expired {
+            info!("sign request {} wasn't approved in time", request);
+            self.reject_sign(request);
+        }
+        self.awaiting_signature.retain(|_, pending| pending.since.elapsed() <= REQUEST_TIMEOUT);
+        self.early_sign_messages.retain(|_, early| early.since.elapsed() <= REQUEST_TIMEOUT);
+    }
+
+    fn retry_waiting(&mut self) {
+        for (peer, messages) in std::mem::take(&mut self.waiting) {
+            for outgoing in messages {
+                self.dispatch(peer, outgoing);
+            }
+        }
+    }
+}
+
+impl NetworkBehaviour for TssBehaviour {
+    type ConnectionHandler = TssHandler;
+    type OutEvent = TssEvent;
+
+    fn new_handler(&mut self) -> TssHandler {
+        TssHandler::new()
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<TssHandler>) {
+        match event {
+            FromSwarm::ConnectionEstablished(ConnectionEstablished { peer_id, .. }) => {
+                self.connected.insert(peer_id);
+                self.dialing.remove(&peer_id);
+                for outgoing in self.waiting.remove(&peer_id).unwrap_or_default() {
+                    self.dispatch(peer_id, outgoing);
+                }
+            },
+            FromSwarm::ConnectionClosed(ConnectionClosed { peer_id, handler, remaining_established, .. }) => {
+                if remaining_established == 0 {
+                    self.connected.remove(&peer_id);
+                }
+                for outgoing in handler.into_unsent() {
+                    self.retry_later(peer_id, outgoing);
+                }
+            },
+            FromSwarm::DialFailure(DialFailure { peer_id: Some(peer_id), .. }) => {
+                self.dialing.remove(&peer_id);
+                if self.connected.contains(&peer_id) {
+                    return;
+                }
+                for outgoing in self.waiting.remove(&peer_id).unwrap_or_default() {
+                    self.retry_later(peer_id, outgoing);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(&mut self, peer_id: PeerId, _: ConnectionId, event: HandlerEvent) {
+        match event {
+            HandlerEvent::Received(message) => self.on_message(peer_id, message),
+            HandlerEvent::Failed(outgoing) => self.retry_later(peer_id, outgoing),
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<TssEvent, TssHandler>> {
+        while let Some(Poll::Ready(Some(output))) = self.key_gen.as_mut().map(|run| run.output.poll_next_unpin(cx)) {
+            self.on_key_gen_output(output);
+        }
+        while let Poll::Ready(Some(output)) = self.sign_output.poll_next_unpin(cx) {
+            self.on_sign_output(output);
+        }
+        while self.retry_timer.poll_unpin(cx).is_ready() {
+            self.retry_timer = Box::pin(async_std::task::sleep(RETRY_INTERVAL));
+            self.retry_waiting();
+            self.expire_requests();
+        }
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(event);
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_ids_forget_the_oldest() {
+        let mut recent = RecentIds::new(2);
+        assert!(recent.insert(1));
+        assert!(!recent.insert(1));
+        assert!(recent.insert(2));
+        assert!(recent.insert(3));
+        assert!(!recent.contains(&1));
+        assert!(recent.contains(&2) && recent.contains(&3));
+        assert_eq!(recent.order.len(), 2);
+    }
+
+    fn waiting_ids(behaviour: &TssBehaviour, peer: &PeerId) -> Vec<u64> {
+        behaviour.waiting.get(peer).map_or_else(Vec::new, |waiting| waiting.iter().map(|outgoing| outgoing.id).collect())
+    }
+
+    #[test]
+    fn unacked_messages_are_retried() {
+        let mut behaviour = TssBehaviour::new(PeerId::random());
+        let peer = PeerId::random();
+        behaviour.send(peer, SessionMessage::KeyGenConfirmation { session: 1, from: 1 });
+        // not connected, so it waits for the dial
+        assert_eq!(waiting_ids(&behaviour, &peer), vec![0]);
+        assert!(matches!(behaviour.events.pop_front(), Some(NetworkBehaviourAction::Dial { .. })));
+
+        behaviour.connected.insert(peer);
+        behaviour.retry_waiting();
+        let Some(NetworkBehaviourAction::NotifyHandler { peer_id, event, .. }) = behaviour.events.pop_front() else {
+            panic!("expected the message to go to the handler");
+        };
+        assert_eq!((peer_id, event.id, event.attempts), (peer, 0, 0));
+
+        // the handler gives it back unacked, it waits for the next retry
+        behaviour.retry_later(peer, event);
+        assert_eq!(behaviour.waiting[&peer][0].attempts, 1);
+        behaviour.retry_waiting();
+        assert!(behaviour.waiting.is_empty());
+        assert!(matches!(behaviour.events.pop_front(), Some(NetworkBehaviourAction::NotifyHandler { .. })));
+    }
+
+    #[test]
+    fn unreachable_peers_fail_the_session() {
+        let mut behaviour = TssBehaviour::new(PeerId::random());
+        let peer = PeerId::random();
+        let message = SessionMessage::KeyGenConfirmation { session: 3, from: 1 };
+        behaviour.retry_later(peer, Outgoing { id: 0, message, attempts: MAX_ATTEMPTS - 1 });
+        assert!(behaviour.waiting.is_empty());
+        let Some(NetworkBehaviourAction::GenerateEvent(TssEvent::SessionFailed { id, error })) = behaviour.events.pop_front() else {
+            panic!("expected the session to fail");
+        };
+        assert_eq!(id, 3);
+        assert!(matches!(error, SessionError::Unreachable(unreachable) if unreachable == peer));
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::io;
+use std::iter;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, FutureExt};
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p::swarm::handler::{ConnectionEvent, DialUpgradeError, FullyNegotiatedInbound, FullyNegotiatedOutbound};
+use libp2p::swarm::{ConnectionHandler, ConnectionHandlerEvent, KeepAlive, SubstreamProtocol};
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_NAME: &[u8] = b"/tss/session/1.0.0";
+// shares and preprocesses are well under this, anything bigger is garbage
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+// how long an idle connection is kept open for the protocol
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const ACK: u8 = 1;
+
+// Everything the members of a session say to each other. Each message goes
+// to the peers that need it and is acknowledged by the receiver.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SessionMessage {
+    // DKG round 1, from the participant with the given index
+    Commitments { session: u64, from: u16, data: Vec<u8> },
+    // DKG round 2, the share encrypted for the receiver
+    SecretShare { session: u64, from: u16, data: Vec<u8> },
+    // the sender's share checked out
+    KeyGenConfirmation { session: u64, from: u16 },
+    // the share `accused` encrypted for the sender is invalid, with the
+    // proof the others need to check that
+    Accusation { session: u64, from: u16, accused: u16, share: Vec<u8>, proof: Option<Vec<u8>> },
+    // ask the members to sign a message under the group key, `signers` are
+    // the participant indices the requester picked
+    SignRequest { request: u64, message: Vec<u8>, signers: Vec<u16> },
+    SignPreprocess { request: u64, from: u16, data: Vec<u8> },
+    SignShare { request: u64, from: u16, data: Vec<u8> },
+    // the finished 64 byte Ed25519 signature, for members outside the signing set
+    Signature { request: u64, signature: Vec<u8> },
+}
+
+impl SessionMessage {
+    // The DKG session or signing request the message belongs to.
+    pub fn id(&self) -> u64 {
+        match self {
+            SessionMessage::Commitments { session, .. }
+            | SessionMessage::SecretShare { session, .. }
+            | SessionMessage::KeyGenConfirmation { session, .. }
+            | SessionMessage::Accusation { session, .. } => *session,
+            SessionMessage::SignRequest { request, .. }
+            | SessionMessage::SignPreprocess { request, .. }
+            | SessionMessage::SignShare { request, .. }
+            | SessionMessage::Signature { request, .. } => *request,
+        }
+    }
+}
+
+// A message on its way to a peer, `id` tells apart the copies in flight.
+#[derive(Debug, Clone)]
+pub struct Outgoing {
+    pub id: u64,
+    pub message: SessionMessage,
+    pub attempts: u32,
+}
+
+#[derive(Debug)]
+pub enum HandlerEvent {
+    Received(SessionMessage),
+    // the substream failed before the peer acknowledged the message
+    Failed(Outgoing),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TssProtocol;
+
+impl UpgradeInfo for TssProtocol {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(PROTOCOL_NAME)
+    }
+}
+
+impl<S> InboundUpgrade<S> for TssProtocol
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Output = SessionMessage;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<SessionMessage>>;
+
+    fn upgrade_inbound(self, mut socket: S, _: Self::Info) -> Self::Future {
+        async move {
+            let data = read_length_prefixed(&mut socket, MAX_MESSAGE_SIZE).await?;
+            let message = serde_json::from_slice(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            socket.write_all(&[ACK]).await?;
+            socket.close().await?;
+            Ok(message)
+        }
+        .boxed()
+    }
+}
+
+impl UpgradeInfo for Outgoing {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(PROTOCOL_NAME)
+    }
+}
+
+impl<S> OutboundUpgrade<S> for Outgoing
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Output = ();
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<()>>;
+
+    fn upgrade_outbound(self, mut socket: S, _: Self::Info) -> Self::Future {
+        async move {
+            write_length_prefixed(&mut socket, serde_json::to_vec(&self.message)?).await?;
+            let mut ack = [0];
+            socket.read_exact(&mut ack).await?;
+            if ack[0] != ACK {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "bad ack"));
+            }
+            socket.close().await
+        }
+        .boxed()
+    }
+}
+
+// Opens one substream per message and waits for the ack on it.
+pub struct TssHandler {
+    pending: VecDeque<Outgoing>,
+    // sent but not acknowledged yet
+    in_flight: HashMap<u64, Outgoing>,
+    events: VecDeque<HandlerEvent>,
+    keep_alive: KeepAlive,
+}
+
+impl TssHandler {
+    pub fn new() -> Self {
+        TssHandler {
+            pending: VecDeque::new(),
+            in_flight: HashMap::new(),
+            events: VecDeque::new(),
+            keep_alive: KeepAlive::Until(Instant::now() + IDLE_TIMEOUT),
+        }
+    }
+
+    // What never made it to the peer, for when the connection goes away.
+    pub fn into_unsent(self) -> Vec<Outgoing> {
+        self.pending.into_iter().chain(self.in_flight.into_values()).collect()
+    }
+
+    fn update_keep_alive(&mut self) {
+        self.keep_alive = if self.pending.is_empty() && self.in_flight.is_empty() {
+            KeepAlive::Until(Instant::now() + IDLE_TIMEOUT)
+        } else {
+            KeepAlive::Yes
+        };
+    }
+}
+
+impl ConnectionHandler for TssHandler {
+    type InEvent = Outgoing;
+    type OutEvent = HandlerEvent;
+    type Error = Infallible;
+    type InboundProtocol = TssProtocol;
+    type OutboundProtocol = Outgoing;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = Outgoing;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<TssProtocol, ()> {
+        SubstreamProtocol::new(TssProtocol, ())
+    }
+
+    fn on_behaviour_event(&mut self, outgoing: Outgoing) {
+        self.pending.push_back(outgoing);
+        self.update_keep_alive();
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<TssProtocol, Outgoing, (), Outgoing>,
+    ) {
+        match event {
+            ConnectionEvent::FullyNegotiatedInbound(FullyNegotiatedInbound { protocol: message, .. }) => {
+                self.events.push_back(HandlerEvent::Received(message));
+            },
+            ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound { info, .. }) => {
+                self.in_flight.remove(&info.id);
+            },
+            ConnectionEvent::DialUpgradeError(DialUpgradeError { info, .. }) => {
+                self.in_flight.remove(&info.id);
+                self.events.push_back(HandlerEvent::Failed(info));
+            },
+            _ => {}
+        }
+        self.update_keep_alive();
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+    ) -> Poll<ConnectionHandlerEvent<Outgoing, Outgoing, HandlerEvent, Infallible>> {
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(ConnectionHandlerEvent::Custom(event));
+        }
+        if let Some(outgoing) = self.pending.pop_front() {
+            self.in_flight.insert(outgoing.id, outgoing.clone());
+            return Poll::Ready(ConnectionHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(outgoing.clone(), outgoing),
+            });
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::os::unix::net::UnixStream;
+    use async_std::task::block_on;
+    use futures::task::noop_waker_ref;
+    use libp2p::swarm::ConnectionHandlerUpgrErr;
+
+    fn outgoing(id: u64) -> Outgoing {
+        Outgoing { id, message: SessionMessage::KeyGenConfirmation { session: 1, from: 2 }, attempts: 0 }
+    }
+
+    fn poll(handler: &mut TssHandler) -> Poll<ConnectionHandlerEvent<Outgoing, Outgoing, HandlerEvent, Infallible>> {
+        handler.poll(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    #[test]
+    fn receiver_acks_the_message() {
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let (sent, received) = block_on(async {
+            futures::join!(outgoing(0).upgrade_outbound(sender, PROTOCOL_NAME), TssProtocol.upgrade_inbound(receiver, PROTOCOL_NAME))
+        });
+        sent.unwrap();
+        assert!(matches!(received.unwrap(), SessionMessage::KeyGenConfirmation { session: 1, from: 2 }));
+    }
+
+    #[test]
+    fn sending_fails_without_an_ack() {
+        let (sender, mut receiver) = UnixStream::pair().unwrap();
+        let sent = block_on(async {
+            let (sent, _) = futures::join!(outgoing(0).upgrade_outbound(sender, PROTOCOL_NAME), async move {
+                read_length_prefixed(&mut receiver, MAX_MESSAGE_SIZE).await.unwrap();
+                drop(receiver);
+            });
+            sent
+        });
+        assert!(sent.is_err());
+    }
+
+    #[test]
+    fn sending_fails_on_a_bad_ack() {
+        let (sender, mut receiver) = UnixStream::pair().unwrap();
+        let sent = block_on(async {
+            let (sent, _) = futures::join!(outgoing(0).upgrade_outbound(sender, PROTOCOL_NAME), async {
+                read_length_prefixed(&mut receiver, MAX_MESSAGE_SIZE).await.unwrap();
+                receiver.write_all(&[ACK + 1]).await.unwrap();
+            });
+            sent
+        });
+        assert!(sent.is_err());
+    }
+
+    #[test]
+    fn acked_messages_are_done() {
+        let mut handler = TssHandler::new();
+        handler.on_behaviour_event(outgoing(7));
+        assert_eq!(handler.connection_keep_alive(), KeepAlive::Yes);
+        let Poll::Ready(ConnectionHandlerEvent::OutboundSubstreamRequest { protocol }) = poll(&mut handler) else {
+            panic!("expected a substream request");
+        };
+        assert_eq!(protocol.info().id, 7);
+        assert!(handler.in_flight.contains_key(&7));
+
+        handler.on_connection_event(ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound { protocol: (), info: outgoing(7) }));
+        assert!(handler.in_flight.is_empty());
+        assert!(poll(&mut handler).is_pending());
+        assert!(handler.into_unsent().is_empty());
+    }
+
+    #[test]
+    fn failed_messages_go_back_to_the_behaviour() {
+        let mut handler = TssHandler::new();
+        handler.on_behaviour_event(outgoing(7));
+        let _ = poll(&mut handler);
+        handler.on_connection_event(ConnectionEvent::DialUpgradeError(DialUpgradeError {
+            info: outgoing(7),
+            error: ConnectionHandlerUpgrErr::Timeout,
+        }));
+        assert!(handler.in_flight.is_empty());
+        let Poll::Ready(ConnectionHandlerEvent::Custom(HandlerEvent::Failed(failed))) = poll(&mut handler) else {
+            panic!("expected the failed message");
+        };
+        assert_eq!(failed.id, 7);
+    }
+
+    #[test]
+    fn unsent_messages_survive_the_connection() {
+        let mut handler = TssHandler::new();
+        handler.on_behaviour_event(outgoing(1));
+        handler.on_behaviour_event(outgoing(2));
+        let _ = poll(&mut handler);
+        let mut unsent: Vec<u64> = handler.into_unsent().iter().map(|outgoing| outgoing.id).collect();
+        unsent.sort_unstable();
+        assert_eq!(unsent, vec![1, 2]);
+    }
+}
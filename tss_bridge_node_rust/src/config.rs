@@ -0,0 +1,320 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use clap::Parser;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use serde::Deserialize;
+use stellar_strkey::StrkeyPublicKeyEd25519;
+
+use crate::stellar::StellarAccount;
+
+const DEFAULT_LISTEN: &str = "/ip4/0.0.0.0/tcp/0";
+const DEFAULT_TOPIC: &str = "tss_main";
+const DEFAULT_NAMESPACE: &str = "tss";
+const DEFAULT_DATA_DIR: &str = "tss_data";
+const DEFAULT_NETWORK_PASSPHRASE: &str = "Test SDF Network ; September 2015";
+// announcements are resent on purpose, which gossipsub warns about every time
+const DEFAULT_LOG_LEVEL: &str = "info,libp2p_gossipsub=error";
+// 2 of 2, what the node always ran with before it was configurable
+const DEFAULT_MEMBERS: u16 = 2;
+
+// Every flag can also be given through its environment variable. Both win
+// over the config file.
+#[derive(Parser, Debug)]
+#[command(version, about = "Threshold signing node for the Stellar bridge")]
+pub struct Cli {
+    /// TOML file with the node settings
+    #[arg(short, long, env = "TSS_CONFIG")]
+    config: Option<PathBuf>,
+    /// Addresses to listen on, comma separated
+    #[arg(long, env = "TSS_LISTEN", value_delimiter = ',')]
+    listen: Option<Vec<Multiaddr>>,
+    /// Peers to join the network through, as addresses ending in /p2p/<peer id>.
+    /// A node without any is a bootstrap node itself.
+    #[arg(long, env = "TSS_BOOTSTRAP", value_delimiter = ',')]
+    bootstrap: Option<Vec<Multiaddr>>,
+    /// Gossipsub topic new members announce themselves on
+    #[arg(long, env = "TSS_TOPIC")]
+    topic: Option<String>,
+    /// Keeps the topics and keys of separate deployments apart
+    #[arg(long, env = "TSS_NAMESPACE")]
+    namespace: Option<String>,
+    /// Number of members that have to sign
+    #[arg(long, env = "TSS_THRESHOLD")]
+    threshold: Option<u16>,
+    /// Number of members holding a key share
+    #[arg(long, env = "TSS_MEMBERS")]
+    members: Option<u16>,
+    /// Where the keystore is kept
+    #[arg(long, env = "TSS_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    /// Passphrase of the Stellar network the bridge runs on
+    #[arg(long, env = "TSS_NETWORK_PASSPHRASE")]
+    network_passphrase: Option<String>,
+    /// Id of the bridge contract the group key signs for
+    #[arg(long, env = "TSS_BRIDGE_CONTRACT")]
+    bridge_contract: Option<String>,
+    /// Stellar account of this node, shared with the other members. Required
+    /// unless this is a bootstrap node, its secret seed goes in TSS_STELLAR_SECRET.
+    #[arg(long, env = "TSS_STELLAR_KEY")]
+    stellar_key: Option<String>,
+    /// env_logger style filter, e.g. `info` or `info,libp2p_gossipsub=debug`
+    #[arg(long, env = "TSS_LOG")]
+    log_level: Option<String>,
+    /// Run without a keystore when TSS_KEYSTORE_PASSPHRASE isn't set. The
+    /// identity and key share are lost on restart.
+    #[arg(long, env = "TSS_EPHEMERAL")]
+    ephemeral: bool,
+}
+
+// The config file, every key is optional.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct FileConfig {
+    listen: Option<Vec<Multiaddr>>,
+    bootstrap: Option<Vec<Multiaddr>>,
+    topic: Option<String>,
+    namespace: Option<String>,
+    threshold: Option<u16>,
+    members: Option<u16>,
+    data_dir: Option<PathBuf>,
+    network_passphrase: Option<String>,
+    bridge_contract: Option<String>,
+    stellar_key: Option<String>,
+    log_level: Option<String>,
+    ephemeral: Option<bool>,
+}
+
+pub struct NodeConfig {
+    pub listen: Vec<Multiaddr>,
+    pub bootstrap: Vec<(PeerId, Multiaddr)>,
+    pub topic: String,
+    pub namespace: String,
+    pub threshold: u16,
+    pub members: u16,
+    pub data_dir: PathBuf,
+    pub network_passphrase: String,
+    pub bridge_contract: String,
+    pub stellar_key: String,
+    pub log_level: String,
+    // only ever taken from the environment, so it stays out of files and
+    // `ps`. Only missing if the node runs ephemeral.
+    pub keystore_passphrase: Option<String>,
+    // `stellar_key` itself, from TSS_STELLAR_SECRET like the passphrase.
+    // Only the bootstrap node runs without it.
+    pub stellar_account: Option<StellarAccount>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "can't read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "invalid config {}: {}", path.display(), e),
+            ConfigError::Invalid(what) => write!(f, "invalid config: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Splits `/ip4/../tcp/../p2p/<peer id>` into the peer and where to reach it.
+fn bootstrap_peer(address: Multiaddr) -> Result<(PeerId, Multiaddr), ConfigError> {
+    let mut dial = address.clone();
+    match dial.pop() {
+        Some(Protocol::P2p(hash)) => {
+            let peer = PeerId::from_multihash(hash)
+                .map_err(|_| ConfigError::Invalid(format!("bad peer id in bootstrap address {}", address)))?;
+            Ok((peer, dial))
+        },
+        _ => Err(ConfigError::Invalid(format!("bootstrap address {} doesn't end in /p2p/<peer id>", address))),
+    }
+}
+
+impl NodeConfig {
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::from_cli(Cli::parse())
+    }
+
+    fn from_cli(cli: Cli) -> Result<Self, ConfigError> {
+        let file = match &cli.config {
+            Some(path) => {
+                let data = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+                toml::from_str(&data).map_err(|e| ConfigError::Parse(path.clone(), e))?
+            },
+            None => FileConfig::default(),
+        };
+
+        let listen = cli.listen.or(file.listen)
+            .unwrap_or_else(|| vec![DEFAULT_LISTEN.parse().unwrap()]);
+        if listen.is_empty() {
+            return Err(ConfigError::Invalid("nothing to listen on".to_string()));
+        }
+        let bootstrap: Vec<_> = cli.bootstrap.or(file.bootstrap).unwrap_or_default()
+            .into_iter()
+            .map(bootstrap_peer)
+            .collect::<Result<_, _>>()?;
+
+        let members = cli.members.or(file.members).unwrap_or(DEFAULT_MEMBERS);
+        // a majority of the members has to sign unless told otherwise
+        let threshold = cli.threshold.or(file.threshold).unwrap_or(members / 2 + 1);
+        if members < 2 || threshold == 0 || threshold > members {
+            return Err(ConfigError::Invalid(format!("can't run a {} of {} scheme", threshold, members)));
+        }
+
+        // members end up in each other's lists under their account, the
+        // bootstrap node never joins a session
+        let stellar_key = cli.stellar_key.or(file.stellar_key).unwrap_or_default();
+        if !bootstrap.is_empty() && StrkeyPublicKeyEd25519::from_string(&stellar_key).is_err() {
+            return Err(ConfigError::Invalid(format!("stellar-key {:?} isn't a Stellar account id", stellar_key)));
+        }
+        // members sign their joins with the account
+        let stellar_account = match std::env::var("TSS_STELLAR_SECRET") {
+            Ok(secret) => Some(StellarAccount::from_secret(&secret)
+                .ok_or_else(|| ConfigError::Invalid("TSS_STELLAR_SECRET isn't a Stellar secret seed".to_string()))?),
+            Err(_) => None,
+        };
+        match &stellar_account {
+            Some(account) if account.id() != stellar_key => {
+                return Err(ConfigError::Invalid(format!("TSS_STELLAR_SECRET is the seed of {}, not of {}", account.id(), stellar_key)));
+            },
+            None if !bootstrap.is_empty() => {
+                return Err(ConfigError::Invalid("TSS_STELLAR_SECRET has to hold the seed of stellar-key".to_string()));
+            },
+            _ => {},
+        }
+
+        // a node that forgets its identity on restart can't rejoin its scheme
+        let keystore_passphrase = std::env::var("TSS_KEYSTORE_PASSPHRASE").ok();
+        let ephemeral = cli.ephemeral || file.ephemeral.unwrap_or(false);
+        if keystore_passphrase.is_none() && !ephemeral {
+            return Err(ConfigError::Invalid(
+                "TSS_KEYSTORE_PASSPHRASE isn't set, pass --ephemeral to run without a keystore".to_string()));
+        }
+
+        Ok(NodeConfig {
+            listen,
+            bootstrap,
+            topic: cli.topic.or(file.topic).unwrap_or_else(|| DEFAULT_TOPIC.to_string()),
+            namespace: cli.namespace.or(file.namespace).unwrap_or_else(|| DEFAULT_NAMESPACE.to_string()),
+            threshold,
+            members,
+            data_dir: cli.data_dir.or(file.data_dir).unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR)),
+            network_passphrase: cli.network_passphrase.or(file.network_passphrase)
+                .unwrap_or_else(|| DEFAULT_NETWORK_PASSPHRASE.to_string()),
+            bridge_contract: cli.bridge_contract.or(file.bridge_contract).unwrap_or_default(),
+            stellar_key,
+            log_level: cli.log_level.or(file.log_level).unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string()),
+            keystore_passphrase,
+            stellar_account,
+        })
+    }
+
+    pub fn is_bootstrap_node(&self) -> bool {
+        self.bootstrap.is_empty()
+    }
+
+    // Topic names are prefixed with the namespace so deployments sharing
+    // a network don't hear each other.
+    pub fn global_topic(&self) -> String {
+        format!("{}/{}", self.namespace, self.topic)
+    }
+
+    pub fn session_topic(&self, session: u64) -> String {
+        format!("{}/tss_session_{}", self.namespace, session)
+    }
+
+    // Binds a generated key to this deployment, the same session in another
+    // namespace or for another contract ends up with an unrelated key.
+    pub fn key_gen_context(&self, session: u64) -> String {
+        format!("{}/{}/{}/{}", self.namespace, self.network_passphrase, self.bridge_contract, session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOTSTRAP: &str = "/ip4/127.0.0.1/tcp/4000/p2p/12D3KooWQ5vye4iLE3qd9JYCey4gQQfwbHh6sBdaLjkt1svYEtMJ";
+
+    fn load(args: &[&str]) -> Result<NodeConfig, ConfigError> {
+        NodeConfig::from_cli(Cli::try_parse_from(["tss_bridge_node_rust"].iter().chain(args)).unwrap())
+    }
+
+    fn config_file(contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tss-config-{}.toml", rand::random::<u64>()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn defaults() {
+        let config = load(&["--ephemeral"]).unwrap();
+        assert_eq!(config.listen, vec![DEFAULT_LISTEN.parse::<Multiaddr>().unwrap()]);
+        assert!(config.is_bootstrap_node());
+        assert_eq!(config.global_topic(), "tss/tss_main");
+        assert_eq!((config.threshold, config.members), (2, 2));
+        assert_eq!(config.data_dir, PathBuf::from(DEFAULT_DATA_DIR));
+        assert_eq!(config.network_passphrase, DEFAULT_NETWORK_PASSPHRASE);
+        assert_eq!(config.log_level, DEFAULT_LOG_LEVEL);
+    }
+
+    #[test]
+    fn flags_win_over_the_file() {
+        let path = config_file(r#"
+            topic = "from_file"
+            namespace = "testnet"
+            members = 5
+            threshold = 4
+            data-dir = "file_data"
+            ephemeral = true
+        "#);
+        let config = load(&["--config", path.to_str().unwrap(), "--topic", "from_flag", "--members", "7"]).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.global_topic(), "testnet/from_flag");
+        assert_eq!((config.threshold, config.members), (4, 7));
+        assert_eq!(config.data_dir, PathBuf::from("file_data"));
+        // untouched by both
+        assert_eq!(config.log_level, DEFAULT_LOG_LEVEL);
+    }
+
+    #[test]
+    fn threshold_defaults_to_a_majority() {
+        let config = load(&["--ephemeral", "--members", "5"]).unwrap();
+        assert_eq!(config.threshold, 3);
+        let config = load(&["--ephemeral", "--members", "4"]).unwrap();
+        assert_eq!(config.threshold, 3);
+    }
+
+    #[test]
+    fn invalid_configs() {
+        assert!(matches!(load(&["--ephemeral", "--members", "1"]), Err(ConfigError::Invalid(_))));
+        assert!(matches!(load(&["--ephemeral", "--threshold", "3"]), Err(ConfigError::Invalid(_))));
+        assert!(matches!(load(&["--ephemeral", "--threshold", "0"]), Err(ConfigError::Invalid(_))));
+        assert!(matches!(load(&["--ephemeral", "--bootstrap", "/ip4/127.0.0.1/tcp/4000"]), Err(ConfigError::Invalid(_))));
+        // members need their Stellar account
+        assert!(matches!(load(&["--ephemeral", "--bootstrap", BOOTSTRAP]), Err(ConfigError::Invalid(_))));
+        assert!(matches!(load(&["--ephemeral", "--bootstrap", BOOTSTRAP, "--stellar-key", "GSTELLAR1"]), Err(ConfigError::Invalid(_))));
+        if std::env::var_os("TSS_KEYSTORE_PASSPHRASE").is_none() {
+            assert!(matches!(load(&[]), Err(ConfigError::Invalid(_))));
+        }
+
+        let path = config_file("listen = []");
+        assert!(matches!(load(&["--ephemeral", "--config", path.to_str().unwrap()]), Err(ConfigError::Invalid(_))));
+        fs::remove_file(&path).unwrap();
+        let path = config_file("members = \"three\"");
+        assert!(matches!(load(&["--ephemeral", "--config", path.to_str().unwrap()]), Err(ConfigError::Parse(..))));
+        fs::remove_file(&path).unwrap();
+        let missing = std::env::temp_dir().join("tss-config-missing.toml");
+        assert!(matches!(load(&["--ephemeral", "--config", missing.to_str().unwrap()]), Err(ConfigError::Io(..))));
+    }
+}
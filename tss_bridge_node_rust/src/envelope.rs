@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libp2p::gossipsub::TopicHash;
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+// keeps envelope signatures from being valid as anything else the key signs
+const SIGNING_DOMAIN: &[u8] = b"tss_bridge_node_rust/envelope/v1";
+
+// What members publish over gossipsub: a message bound to its session and
+// signed by the sender's node key, the Ed25519 key its peer id is made of.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Envelope {
+    pub session: u64,
+    // counts up with every message the sender publishes
+    pub seq: u64,
+    // protobuf encoded, hex
+    public_key: String,
+    // the message as JSON
+    pub payload: String,
+    signature: String,
+}
+
+#[derive(Debug)]
+pub enum EnvelopeError {
+    Malformed(String),
+    BadKey,
+    // signed with a key that isn't the sender's
+    WrongSender,
+    BadSignature,
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::Malformed(e) => write!(f, "malformed envelope: {}", e),
+            EnvelopeError::BadKey => write!(f, "invalid public key"),
+            EnvelopeError::WrongSender => write!(f, "key doesn't belong to the sender"),
+            EnvelopeError::BadSignature => write!(f, "invalid signature"),
+        }
+    }
+}
+
+fn signed_bytes(session: u64, seq: u64, payload: &str) -> Vec<u8> {
+    let mut bytes = SIGNING_DOMAIN.to_vec();
+    bytes.extend_from_slice(&session.to_le_bytes());
+    bytes.extend_from_slice(&seq.to_le_bytes());
+    bytes.extend_from_slice(payload.as_bytes());
+    bytes
+}
+
+impl Envelope {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, EnvelopeError> {
+        serde_json::from_slice(data).map_err(|e| EnvelopeError::Malformed(e.to_string()))
+    }
+
+    // Checks the envelope was signed by `sender`.
+    pub fn verify(&self, sender: &PeerId) -> Result<(), EnvelopeError> {
+        let key = hex::decode(&self.public_key).map_err(|_| EnvelopeError::BadKey)?;
+        let key = PublicKey::from_protobuf_encoding(&key).map_err(|_| EnvelopeError::BadKey)?;
+        if PeerId::from(key.clone()) != *sender {
+            return Err(EnvelopeError::WrongSender);
+        }
+        let signature = hex::decode(&self.signature).map_err(|_| EnvelopeError::BadSignature)?;
+        if !key.verify(&signed_bytes(self.session, self.seq, &self.payload), &signature) {
+            return Err(EnvelopeError::BadSignature);
+        }
+        Ok(())
+    }
+}
+
+// Puts our messages into envelopes.
+pub struct Sealer {
+    keypair: Keypair,
+    seq: u64,
+}
+
+impl Sealer {
+    pub fn new(keypair: Keypair) -> Self {
+        // starting at the clock keeps the numbers growing over restarts, the
+        // others would ignore us until we caught up otherwise
+        let seq = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_micros() as u64);
+        Sealer { keypair, seq }
+    }
+
+    pub fn seal(&mut self, session: u64, payload: String) -> Envelope {
+        self.seq += 1;
+        let signature = self.keypair.sign(&signed_bytes(session, self.seq, &payload))
+            .expect("Ed25519 signing can't fail");
+        Envelope {
+            session,
+            seq: self.seq,
+            public_key: hex::encode(self.keypair.public().to_protobuf_encoding()),
+            payload,
+            signature: hex::encode(signature),
+        }
+    }
+}
+
+// The last sequence number each sender used on each topic. Gossipsub keeps
+// replaying messages for a while, anything not newer than what we've seen
+// is one of those or an attempt to replay it.
+#[derive(Default)]
+pub struct Sequences {
+    last: HashMap<(PeerId, TopicHash), u64>,
+}
+
+impl Sequences {
+    // Returns whether `seq` is new and remembers it if it is.
+    pub fn advance(&mut self, sender: PeerId, topic: TopicHash, seq: u64) -> bool {
+        let last = self.last.entry((sender, topic)).or_default();
+        if seq <= *last {
+            return false;
+        }
+        *last = seq;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_envelopes_verify() {
+        let keypair = Keypair::generate_ed25519();
+        let sender = PeerId::from(keypair.public());
+        let mut sealer = Sealer::new(keypair);
+        let first = sealer.seal(7, "{}".to_string());
+        let second = sealer.seal(7, "{}".to_string());
+        assert!(second.seq > first.seq);
+        for envelope in [first, second] {
+            let data = serde_json::to_vec(&envelope).unwrap();
+            Envelope::from_bytes(&data).unwrap().verify(&sender).unwrap();
+        }
+    }
+
+    #[test]
+    fn verify_rejects_tampering() {
+        let keypair = Keypair::generate_ed25519();
+        let sender = PeerId::from(keypair.public());
+        let mut sealer = Sealer::new(keypair);
+
+        let envelope = sealer.seal(7, "{}".to_string());
+        assert!(matches!(envelope.verify(&PeerId::random()), Err(EnvelopeError::WrongSender)));
+
+        for tamper in [
+            |envelope: &mut Envelope| envelope.session += 1,
+            |envelope: &mut Envelope| envelope.seq += 1,
+            |envelope: &mut Envelope| envelope.payload.push(' '),
+        ] {
+            let mut envelope = sealer.seal(7, "{}".to_string());
+            tamper(&mut envelope);
+            assert!(matches!(envelope.verify(&sender), Err(EnvelopeError::BadSignature)));
+        }
+
+        let mut bad_key = sealer.seal(7, "{}".to_string());
+        bad_key.public_key = "00".to_string();
+        assert!(matches!(bad_key.verify(&sender), Err(EnvelopeError::BadKey)));
+        assert!(matches!(Envelope::from_bytes(b"not json"), Err(EnvelopeError::Malformed(_))));
+    }
+
+    #[test]
+    fn sequences_only_advance() {
+        let mut sequences = Sequences::default();
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let (global, session) = (TopicHash::from_raw("global"), TopicHash::from_raw("session"));
+
+        assert!(sequences.advance(alice, global.clone(), 5));
+        assert!(!sequences.advance(alice, global.clone(), 5));
+        assert!(!sequences.advance(alice, global.clone(), 4));
+        assert!(sequences.advance(alice, global.clone(), 9));
+        // kept per sender and topic
+        assert!(sequences.advance(bob, global.clone(), 1));
+        assert!(sequences.advance(alice, session.clone(), 1));
+        assert!(!sequences.advance(alice, global, 8));
+        assert!(!sequences.advance(alice, session, 1));
+    }
+}
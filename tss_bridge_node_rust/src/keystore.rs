@@ -0,0 +1,229 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use libp2p::identity::Keypair;
+use libp2p::PeerId;
+use modular_frost::curve::Ed25519;
+use modular_frost::{ThresholdCore, ThresholdKeys};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+const KEYSTORE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+// The scheme this node holds a share of.
+pub struct StoredScheme {
+    pub session: u64,
+    // bumped every time the members generate a new key
+    pub epoch: u64,
+    // both indexed by participant index - 1
+    pub members: Vec<PeerId>,
+    pub stellar_keys: Vec<String>,
+    pub keys: ThresholdKeys<Ed25519>,
+}
+
+// Everything a node needs to come back as the same member after a restart.
+pub struct KeyStore {
+    pub identity: Keypair,
+    pub scheme: Option<StoredScheme>,
+}
+
+#[derive(Debug)]
+pub enum KeyStoreError {
+    Io(io::Error),
+    // the passphrase is wrong or the file was tampered with
+    Decrypt,
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for KeyStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyStoreError::Io(e) => write!(f, "{}", e),
+            KeyStoreError::Decrypt => write!(f, "wrong passphrase or damaged keystore"),
+            KeyStoreError::Corrupt(what) => write!(f, "corrupt keystore: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for KeyStoreError {}
+
+impl From<io::Error> for KeyStoreError {
+    fn from(e: io::Error) -> Self {
+        KeyStoreError::Io(e)
+    }
+}
+
+// The plaintext, before it's encrypted.
+#[derive(Serialize, Deserialize)]
+struct Contents {
+    identity: Vec<u8>,
+    scheme: Option<SchemeContents>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SchemeContents {
+    session: u64,
+    epoch: u64,
+    members: Vec<Vec<u8>>,
+    // not in keystores written before members had Stellar keys
+    #[serde(default)]
+    stellar_keys: Vec<String>,
+    core: Vec<u8>,
+}
+
+// What goes on disk, only the KDF salt and the nonce are in the clear.
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    version: u32,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, KeyStoreError> {
+    let mut key = Zeroizing::new([0; 32]);
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|_| KeyStoreError::Corrupt("bad salt"))?;
+    Ok(key)
+}
+
+impl KeyStore {
+    // A fresh identity and no scheme yet.
+    pub fn generate() -> Self {
+        KeyStore { identity: Keypair::generate_ed25519(), scheme: None }
+    }
+
+    // `Ok(None)` when nothing has been stored at `path` yet.
+    pub fn load(path: &Path, passphrase: &str) -> Result<Option<Self>, KeyStoreError> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let sealed: Sealed = serde_json::from_slice(&data).map_err(|_| KeyStoreError::Corrupt("not a keystore"))?;
+        if sealed.version != KEYSTORE_VERSION {
+            return Err(KeyStoreError::Corrupt("unknown version"));
+        }
+        if sealed.nonce.len() != NONCE_LEN {
+            return Err(KeyStoreError::Corrupt("bad nonce"));
+        }
+
+        let key = derive_key(passphrase, &sealed.salt)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+        let plaintext = Zeroizing::new(
+            cipher.decrypt(XNonce::from_slice(&sealed.nonce), sealed.ciphertext.as_ref())
+                .map_err(|_| KeyStoreError::Decrypt)?,
+        );
+        let contents: Contents = serde_json::from_slice(&plaintext).map_err(|_| KeyStoreError::Corrupt("bad contents"))?;
+
+        let identity = Keypair::from_protobuf_encoding(&contents.identity)
+            .map_err(|_| KeyStoreError::Corrupt("bad identity"))?;
+        let scheme = match contents.scheme {
+            Some(scheme) => {
+                let core = ThresholdCore::<Ed25519>::deserialize(&mut &scheme.core[..])
+                    .map_err(|_| KeyStoreError::Corrupt("bad key share"))?;
+                let members = scheme.members.iter()
+                    .map(|peer| PeerId::from_bytes(peer))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| KeyStoreError::Corrupt("bad member"))?;
+                Some(StoredScheme {
+                    session: scheme.session,
+                    epoch: scheme.epoch,
+                    members,
+                    stellar_keys: scheme.stellar_keys,
+                    keys: ThresholdKeys::new(core),
+                })
+            },
+            None => None,
+        };
+        Ok(Some(KeyStore { identity, scheme }))
+    }
+
+    // Encrypts under a fresh salt and nonce and replaces the file in one go.
+    pub fn save(&self, path: &Path, passphrase: &str) -> Result<(), KeyStoreError> {
+        let contents = Contents {
+            identity: self.identity.to_protobuf_encoding().map_err(|_| KeyStoreError::Corrupt("bad identity"))?,
+            scheme: self.scheme.as_ref().map(|scheme| SchemeContents {
+                session: scheme.session,
+                epoch: scheme.epoch,
+                members: scheme.members.iter().map(|peer| peer.to_bytes()).collect(),
+                stellar_keys: scheme.stellar_keys.clone(),
+                core: scheme.keys.serialize(),
+            }),
+        };
+        let plaintext = Zeroizing::new(serde_json::to_vec(&contents).map_err(io::Error::from)?);
+
+        let mut salt = vec![0; SALT_LEN];
+        let mut nonce = vec![0; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+        let key = derive_key(passphrase, &salt)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+        let ciphertext = cipher.encrypt(XNonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|_| KeyStoreError::Corrupt("encryption failed"))?;
+        let sealed = Sealed { version: KEYSTORE_VERSION, salt, nonce, ciphertext };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&sealed).map_err(io::Error::from)?)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+        }
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("tss-keystore-{}", rand::random::<u64>())).join("keystore.json")
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = scratch_path();
+        assert!(KeyStore::load(&path, "hunter2").unwrap().is_none());
+
+        let keystore = KeyStore::generate();
+        keystore.save(&path, "hunter2").unwrap();
+        let loaded = KeyStore::load(&path, "hunter2").unwrap().unwrap();
+        assert_eq!(PeerId::from(loaded.identity.public()), PeerId::from(keystore.identity.public()));
+        assert!(loaded.scheme.is_none());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn load_with_the_wrong_passphrase() {
+        let path = scratch_path();
+        KeyStore::generate().save(&path, "hunter2").unwrap();
+        assert!(matches!(KeyStore::load(&path, "hunter3"), Err(KeyStoreError::Decrypt)));
+        assert!(matches!(KeyStore::load(&path, ""), Err(KeyStoreError::Decrypt)));
+
+        // a damaged file is no better
+        let mut sealed: Sealed = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        sealed.ciphertext[0] ^= 1;
+        fs::write(&path, serde_json::to_vec(&sealed).unwrap()).unwrap();
+        assert!(matches!(KeyStore::load(&path, "hunter2"), Err(KeyStoreError::Decrypt)));
+
+        fs::write(&path, b"not a keystore").unwrap();
+        assert!(matches!(KeyStore::load(&path, "hunter2"), Err(KeyStoreError::Corrupt(_))));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
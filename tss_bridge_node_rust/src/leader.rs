@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+
+// a leader we haven't heard from for this long gets replaced
+pub const LEADER_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Peers are ordered like in the member list.
+pub fn precedes(a: &PeerId, b: &PeerId) -> bool {
+    a.to_base58() < b.to_base58()
+}
+
+// Who leads the session. Members heartbeat the term and the leader they
+// follow. When the leader goes quiet the lowest peer id among the members
+// still alive takes over in the next term, so everyone ends up following
+// the same node. Terms only ever move up one at a time, and a member can
+// only move us to the next one with the leader we'd have picked ourselves,
+// once the old one went quiet here too or most members moved on.
+#[derive(Default)]
+pub struct Leadership {
+    term: u64,
+    leader: Option<PeerId>,
+    // when we last heard from each member
+    last_seen: HashMap<PeerId, Instant>,
+    // leaders replaced for going quiet and members blamed for a failed DKG.
+    // Gossipsub keeps replaying their old messages for a while, so they
+    // stay out of the session.
+    deposed: HashSet<PeerId>,
+    // the term and leader each member heartbeats last
+    votes: HashMap<PeerId, (u64, PeerId)>,
+}
+
+impl Leadership {
+    // Term 0 is led by whoever created the session.
+    pub fn new(leader: PeerId) -> Self {
+        let mut leadership = Leadership { term: 0, leader: Some(leader), ..Default::default() };
+        // give them a full timeout before we start doubting them
+        leadership.heard_from(leader);
+        leadership
+    }
+
+    pub fn term(&self) -> u64 {
+        self.term
+    }
+
+    pub fn leader(&self) -> Option<PeerId> {
+        self.leader
+    }
+
+    pub fn is_leader(&self, peer: &PeerId) -> bool {
+        self.leader.as_ref() == Some(peer)
+    }
+
+    pub fn is_deposed(&self, peer: &PeerId) -> bool {
+        self.deposed.contains(peer)
+    }
+
+    // Keeps `peer` out of the session. If they lead it the next `check`
+    // hands it to someone else.
+    pub fn depose(&mut self, peer: PeerId) {
+        self.last_seen.remove(&peer);
+        self.deposed.insert(peer);
+    }
+
+    pub fn heard_from(&mut self, peer: PeerId) {
+        if !self.deposed.contains(&peer) {
+            self.last_seen.insert(peer, Instant::now());
+        }
+    }
+
+    pub fn is_alive(&self, peer: &PeerId) -> bool {
+        self.last_seen.get(peer).map_or(false, |seen| seen.elapsed() < LEADER_TIMEOUT)
+    }
+
+    // Whether `peer` could lead, as far as we can tell. We're always alive
+    // to ourselves.
+    fn is_candidate(&self, peer: &PeerId, local_peer_id: PeerId, members: &[PeerId]) -> bool {
+        members.contains(peer) && !self.deposed.contains(peer) && (*peer == local_peer_id || self.is_alive(peer))
+    }
+
+    // The lowest of `members` that can take over from the current leader.
+    fn successor(&self, local_peer_id: PeerId, members: &[PeerId]) -> Option<PeerId> {
+        members.iter()
+            .filter(|peer| self.leader != Some(**peer) && self.is_candidate(peer, local_peer_id, members))
+            .min_by_key(|peer| peer.to_base58())
+            .cloned()
+    }
+
+    // `from` follows `leader` in `term`. `members` are the session's
+    // members, us included. Returns the leader we followed before if that
+    // made us switch.
+    pub fn observe(&mut self, from: PeerId, term: u64, leader: PeerId, local_peer_id: PeerId, members: &[PeerId]) -> Option<PeerId> {
+        if members.contains(&from) && !self.deposed.contains(&from) {
+            self.votes.insert(from, (term, leader));
+        }
+        if self.leader == Some(leader) {
+            return None;
+        }
+        if term == self.term {
+            // two members can elect different leaders for the same term when
+            // they disagree about who's alive, the lower live one wins
+            let better = self.leader.map_or(true, |current| precedes(&leader, &current));
+            if !better || !self.is_candidate(&leader, local_peer_id, members) {
+                return None;
+            }
+            self.heard_from(leader);
+            // a lost tie doesn't mean they're gone
+            return self.leader.replace(leader);
+        }
+        if term != self.term.checked_add(1)? || self.successor(local_peer_id, members) != Some(leader) {
+            return None;
+        }
+        let quiet = self.leader.map_or(true, |current| !self.is_alive(&current));
+        let votes = self.votes.iter()
+            .filter(|(voter, vote)| members.contains(voter) && !self.deposed.contains(voter) && **vote == (term, leader))
+            .count();
+        if !quiet && votes < members.len() / 2 + 1 {
+            return None;
+        }
+        self.term = term;
+        self.heard_from(leader);
+        let previous = self.leader.replace(leader);
+        if let Some(previous) = previous {
+            self.deposed.insert(previous);
+        }
+        previous
+    }
+
+    // Replaces a leader that went quiet with the lowest of `members` that's
+    // still alive. `members` has to include us. Returns the leader that was
+    // replaced.
+    pub fn check(&mut self, local_peer_id: PeerId, members: &[PeerId]) -> Option<PeerId> {
+        let leader = self.leader?;
+        if leader == local_peer_id || self.is_alive(&leader) {
+            return None;
+        }
+        let next = self.successor(local_peer_id, members)?;
+        self.term = self.term.checked_add(1)?;
+        self.deposed.insert(leader);
+        self.leader = Some(next);
+        self.heard_from(next);
+        Some(leader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `n` peers, lowest first
+    fn peers(n: usize) -> Vec<PeerId> {
+        let mut peers: Vec<PeerId> = (0..n).map(|_| PeerId::random()).collect();
+        peers.sort_by_key(|peer| peer.to_base58());
+        peers
+    }
+
+    #[test]
+    fn observe_follows_the_successor_of_a_quiet_leader() {
+        let peers = peers(3);
+        // never heard from the leader
+        let mut leadership = Leadership { leader: Some(peers[0]), ..Default::default() };
+        leadership.heard_from(peers[1]);
+        assert_eq!(leadership.observe(peers[1], 1, peers[1], peers[2], &peers), Some(peers[0]));
+        assert_eq!(leadership.term(), 1);
+        assert!(leadership.is_leader(&peers[1]));
+        assert!(leadership.is_deposed(&peers[0]));
+        // old terms and who we already follow change nothing
+        assert_eq!(leadership.observe(peers[2], 0, peers[2], peers[2], &peers), None);
+        assert_eq!(leadership.observe(peers[1], 1, peers[1], peers[2], &peers), None);
+        assert!(leadership.is_leader(&peers[1]));
+    }
+
+    #[test]
+    fn observe_ignores_an_arbitrary_leader() {
+        let peers = peers(4);
+        let mut leadership = Leadership { leader: Some(peers[0]), ..Default::default() };
+        for peer in &peers[1..] {
+            leadership.heard_from(*peer);
+        }
+        // peers[1] is next, not peers[2]
+        assert_eq!(leadership.observe(peers[3], 1, peers[2], peers[3], &peers), None);
+        // nor anyone outside the session
+        assert_eq!(leadership.observe(peers[3], 1, PeerId::random(), peers[3], &peers), None);
+        assert_eq!(leadership.term(), 0);
+        assert!(leadership.is_leader(&peers[0]));
+        assert!(!leadership.is_deposed(&peers[0]));
+    }
+
+    #[test]
+    fn observe_keeps_a_live_leader_without_a_quorum() {
+        let peers = peers(5);
+        let mut leadership = Leadership::new(peers[0]);
+        for peer in &peers[1..] {
+            leadership.heard_from(*peer);
+        }
+        // three of five have to move on, voting twice doesn't count
+        assert_eq!(leadership.observe(peers[1], 1, peers[1], peers[4], &peers), None);
+        assert_eq!(leadership.observe(peers[1], 1, peers[1], peers[4], &peers), None);
+        assert_eq!(leadership.observe(peers[2], 1, peers[1], peers[4], &peers), None);
+        assert!(leadership.is_leader(&peers[0]));
+        assert_eq!(leadership.observe(peers[3], 1, peers[1], peers[4], &peers), Some(peers[0]));
+        assert!(leadership.is_leader(&peers[1]));
+        assert!(leadership.is_deposed(&peers[0]));
+    }
+
+    #[test]
+    fn observe_ignores_skipped_terms() {
+        let peers = peers(2);
+        let mut leadership = Leadership { leader: Some(peers[0]), ..Default::default() };
+        leadership.heard_from(peers[1]);
+        assert_eq!(leadership.observe(peers[1], 2, peers[1], peers[1], &peers), None);
+        assert_eq!(leadership.observe(peers[1], u64::MAX, peers[1], peers[1], &peers), None);
+        assert_eq!(leadership.term(), 0);
+        assert!(leadership.is_leader(&peers[0]));
+    }
+
+    #[test]
+    fn observe_breaks_ties_by_peer_id() {
+        let peers = peers(3);
+        let mut leadership = Leadership { term: 1, leader: Some(peers[1]), ..Default::default() };
+        // not while the lower one is quiet
+        assert_eq!(leadership.observe(peers[2], 1, peers[0], peers[2], &peers), None);
+        leadership.heard_from(peers[0]);
+        assert_eq!(leadership.observe(peers[2], 1, peers[2], peers[2], &peers), None);
+        assert_eq!(leadership.observe(peers[0], 1, peers[0], peers[2], &peers), Some(peers[1]));
+        assert!(leadership.is_leader(&peers[0]));
+        // a lost tie isn't a reason to keep them out
+        assert!(!leadership.is_deposed(&peers[1]));
+    }
+
+    #[test]
+    fn check_replaces_a_quiet_leader() {
+        let peers = peers(4);
+        // never heard from the leader, nor from peers[1]
+        let mut leadership = Leadership { term: 3, leader: Some(peers[0]), ..Default::default() };
+        leadership.heard_from(peers[2]);
+        assert_eq!(leadership.check(peers[3], &peers), Some(peers[0]));
+        assert_eq!(leadership.term(), 4);
+        assert!(leadership.is_leader(&peers[2]));
+        assert!(leadership.is_deposed(&peers[0]));
+        // and keeps a live one
+        assert_eq!(leadership.check(peers[3], &peers), None);
+        assert!(leadership.is_leader(&peers[2]));
+    }
+
+    #[test]
+    fn check_never_replaces_us() {
+        let peers = peers(2);
+        let mut leadership = Leadership { leader: Some(peers[1]), ..Default::default() };
+        assert_eq!(leadership.check(peers[1], &peers), None);
+        assert!(leadership.is_leader(&peers[1]));
+    }
+
+    #[test]
+    fn check_stops_at_the_last_term() {
+        let peers = peers(2);
+        let mut leadership = Leadership { term: u64::MAX, leader: Some(peers[0]), ..Default::default() };
+        assert_eq!(leadership.check(peers[1], &peers), None);
+        assert_eq!(leadership.term(), u64::MAX);
+        assert!(leadership.is_leader(&peers[0]));
+    }
+
+    #[test]
+    fn deposed_peers_stay_dead() {
+        let peers = peers(2);
+        let mut leadership = Leadership::new(peers[0]);
+        leadership.heard_from(peers[1]);
+        assert!(leadership.is_alive(&peers[1]));
+        leadership.depose(peers[1]);
+        leadership.heard_from(peers[1]);
+        assert!(!leadership.is_alive(&peers[1]));
+    }
+}
@@ -1,125 +1,301 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
-use std::thread;
+use std::path::Path;
+use std::time::Duration;
 
-use crossbeam::channel::unbounded;
+use futures::channel::mpsc;
 use futures::prelude::*;
+use futures::select;
 use libp2p::gossipsub::{
-    GossipsubEvent, IdentTopic as Topic,
+    GossipsubEvent, GossipsubMessage, IdentTopic as Topic, MessageAcceptance,
 };
-use libp2p::swarm::{Swarm, SwarmEvent};
-use libp2p::{identity, Multiaddr, PeerId};
+use libp2p::identify;
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::{DialError, Swarm, SwarmEvent};
+use libp2p::{identity, PeerId};
+use log::{debug, error, info, warn};
 mod behaviour;
+mod config;
+mod envelope;
+mod keystore;
+mod leader;
+mod membership;
+mod signing;
+mod stellar;
 mod tss;
 use behaviour::main_behaviour::{Behaviour, Event};
+use behaviour::tss_behaviour::{Config, TssEvent};
+use config::NodeConfig;
+use envelope::{Envelope, Sealer, Sequences};
+use keystore::{KeyStore, StoredScheme};
+use leader::{precedes, Leadership};
+use membership::MemberList;
 use modular_frost::ThresholdKeys;
 use modular_frost::curve::Ed25519;
 
-use crate::tss::{KeyGenThreadMessage, KeyGenProcess};
-
 
 
 struct TssMember {
     stellar_key: String,
+    peer_id: PeerId,
     index: u16,
 }
 struct TssScheme {
     members: Vec<TssMember>,
     thresholdkeys: ThresholdKeys<Ed25519>,
+    epoch: u64,
 }
+// Session membership, over gossipsub. The DKG and signing themselves run
+// point to point in the tss behaviour.
 #[derive(serde::Deserialize,serde::Serialize,Debug)]
 enum TssMessage{
     Join(u64),
-    // saying that we will rerun the algo and join a new frost schem.
-    Create(u64),
-    // to say that we joined
-    Joined(u64)
+    // saying that we will rerun the algo and join a new frost schem. Nodes
+    // configured for another size or threshold stay out.
+    Create { session: u64, members: u16, threshold: u16 },
+    // to say that we joined, sent on the session topic. Signed by the
+    // Stellar account so nobody joins with someone else's.
+    Joined { session: u64, stellar_key: String, signature: String },
+    // the leader's final member list, everyone takes their index from it
+    Members(MemberList),
+    // a member agrees to run the DKG with the list with this digest
+    Accept { session: u64, digest: String },
+    // sent by every member each tick, with the leader they follow
+    Heartbeat { session: u64, term: u64, leader: String },
 }
 
+impl TssMessage {
+    // Its envelope has to be for the same session.
+    fn session(&self) -> u64 {
+        match self {
+            TssMessage::Join(session)
+            | TssMessage::Create { session, .. }
+            | TssMessage::Joined { session, .. }
+            | TssMessage::Accept { session, .. }
+            | TssMessage::Heartbeat { session, .. } => *session,
+            TssMessage::Members(list) => list.session,
+        }
+    }
+
+    // Takes the message out of its envelope. Forged or malformed ones are
+    // rejected, which gossipsub holds against the peer that sent them to
+    // us. Replays are only ignored, gossipsub itself replays messages.
+    fn open(message: &GossipsubMessage, config: &NodeConfig, sequences: &mut Sequences) -> Result<Self, MessageAcceptance> {
+        let reject = |why: String| {
+            warn!("rejecting a message from {:?}: {}", message.source, why);
+            MessageAcceptance::Reject
+        };
+        // strict validation has gossipsub check the source signed it
+        let Some(source) = message.source else {
+            return Err(reject("no source".to_string()));
+        };
+        let envelope = Envelope::from_bytes(&message.data).map_err(|e| reject(e.to_string()))?;
+        envelope.verify(&source).map_err(|e| reject(e.to_string()))?;
+        let parsed: TssMessage = serde_json::from_str(&envelope.payload)
+            .map_err(|e| reject(format!("malformed message: {}", e)))?;
+        if parsed.session() != envelope.session {
+            return Err(reject(format!("envelope is for session {}", envelope.session)));
+        }
+        // looking for a session happens on the global topic, the rest on
+        // the session's own
+        let topic = match parsed {
+            TssMessage::Join(_) | TssMessage::Create { .. } => config.global_topic(),
+            _ => config.session_topic(envelope.session),
+        };
+        if Topic::new(topic).hash() != message.topic {
+            return Err(reject(format!("{:?} sent on {}", parsed, message.topic)));
+        }
+        if let TssMessage::Joined { session, stellar_key, signature } = &parsed {
+            if !stellar::verify_joined(stellar_key, &source, *session, signature) {
+                return Err(reject(format!("not signed by {}", stellar_key)));
+            }
+        }
+        if !sequences.advance(source, message.topic.clone(), envelope.seq) {
+            return Err(MessageAcceptance::Ignore);
+        }
+        Ok(parsed)
+    }
+}
+
+#[derive(Clone, Copy)]
 enum State{
     Joining(u64),
     Joined(u64),
+    // got the member list, waiting for everyone on it to accept it
+    Agreeing(u64),
     KeyGenStep1(u64),
     Ready_for_signing(u64)
 }
+
+const RESEND_INTERVAL: Duration = Duration::from_secs(5);
+const KEYSTORE_FILE: &str = "keystore.json";
+
+// What we know about the session we're in. Everything but the leadership
+// is only needed until its key is generated.
+#[derive(Default)]
+struct SessionSetup {
+    // only the leader gets to publish the member list
+    leadership: Leadership,
+    // peer -> Stellar key of everyone who joined, everyone keeps track so
+    // a new leader can carry on where the old one stopped
+    joined: BTreeMap<PeerId, String>,
+    members: Option<MemberList>,
+    // the digest of the member list each peer accepted, these can arrive
+    // before the list itself
+    accepts: HashMap<PeerId, String>,
+    // members a failed DKG blamed. The next list goes without them and is
+    // that much shorter.
+    dropped: HashSet<PeerId>,
+}
+
+impl SessionSetup {
+    // How many members the next list needs out of the configured `members`.
+    fn size(&self, members: u16) -> u16 {
+        members.saturating_sub(self.dropped.len() as u16)
+    }
+
+    // Who joined and goes on the next list.
+    fn candidates(&self) -> BTreeMap<PeerId, String> {
+        self.joined.iter()
+            .filter(|(peer, _)| !self.dropped.contains(peer))
+            .map(|(peer, stellar_key)| (*peer, stellar_key.clone()))
+            .collect()
+    }
+}
+
+fn publish(swarm: &mut Swarm<Behaviour>, sealer: &mut Sealer, topic: &Topic, message: &TssMessage) {
+    let envelope = sealer.seal(message.session(), serde_json::to_string(message).unwrap());
+    let data = serde_json::to_string(&envelope).unwrap();
+    if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic.clone(), data) {
+        warn!("failed to publish {:?}: {:?}", message, e);
+    }
+}
+
+fn save_keystore(keystore: &KeyStore, path: &Path, passphrase: Option<&str>) {
+    if let Some(passphrase) = passphrase {
+        if let Err(e) = keystore.save(path, passphrase) {
+            warn!("failed to save the keystore: {}", e);
+        }
+    }
+}
+
 #[async_std::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let local_key = identity::Keypair::generate_ed25519();
+    let config = NodeConfig::load()?;
+    env_logger::Builder::new().parse_filters(&config.log_level).init();
+
+    let keystore_path = config.data_dir.join(KEYSTORE_FILE);
+    let passphrase = config.keystore_passphrase.clone();
+    let mut keystore = match passphrase.as_deref() {
+        Some(passphrase) => KeyStore::load(&keystore_path, passphrase)?.unwrap_or_else(KeyStore::generate),
+        None => {
+            warn!("running ephemeral, the identity and key share won't survive a restart");
+            KeyStore::generate()
+        }
+    };
+    save_keystore(&keystore, &keystore_path, passphrase.as_deref());
+
+    let local_key = keystore.identity.clone();
     let local_peer_id = PeerId::from(local_key.public().clone());
-    println!("Local peer id: {:?}", local_peer_id);
+    info!("Local peer id: {:?}", local_peer_id);
+    let mut sealer = Sealer::new(local_key.clone());
+    let mut sequences = Sequences::default();
 
     let transport = libp2p::development_transport(local_key.clone()).await?;
 
     let mut behaviour = Behaviour::new(local_key.clone(), local_key.public().clone());
-    let mut is_bootstrap_node = false;
-    let global_topic = Topic::new("tss_main");
-
-    if let (Some(addr), Some(peerid)) = (std::env::args().nth(1), std::env::args().nth(2)) {
-        let remote: Multiaddr = addr.parse()?;
-        let peer: PeerId = peerid.parse()?;
-        behaviour.kademelia.add_address(&peer, remote);
-        behaviour.kademelia.bootstrap().unwrap();
-        behaviour.gossipsub.add_explicit_peer(&peer);
-        // swarm.dial(remote)?;
-        // println!("Dialed {}", addr)
-    } else {
-        is_bootstrap_node =true;
-        behaviour.kademelia.bootstrap();
+    let is_bootstrap_node = config.is_bootstrap_node();
+    let global_topic = Topic::new(config.global_topic());
+
+    for (peer, address) in &config.bootstrap {
+        behaviour.kademelia.add_address(peer, address.clone());
+        behaviour.gossipsub.add_explicit_peer(peer);
     }
+    // fails without any peers to ask, which is fine for a bootstrap node
+    let _ = behaviour.kademelia.bootstrap();
 
     let mut swarm = Swarm::with_async_std_executor(transport, behaviour, local_peer_id);
-    swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+    for address in &config.listen {
+        swarm.listen_on(address.clone())?;
+    }
     // if !is_bootstrap_node{
-        swarm.behaviour_mut().gossipsub.subscribe(&global_topic).unwrap();
+        swarm.behaviour_mut().subscribe(&global_topic);
     // }
 
-    // Dial the peer identified by the multi-address given as the second
-    // command-line argument, if any.
     let mut tss: Option<TssScheme> = None;
 
-    let get_tss_members_count = |tss: Option<&TssScheme>|{
-        match tss {
-            Some(tss) => {
-                tss.members.len()
-            },
-            None => {
-                0 // me and the other dude
-            },
-        }
-    };
-    
-    let mut is_leader = false;
+    let mut join_id: u64 = rand::random();
 
-    let should_be_leader = |is_leader_param| {
-        if let None = tss{
-            true
-        }else if is_leader_param{
-            true
-        }else{
-            false
-        }
-    };
-
-
-
-    let join_id: u64 = rand::random();
-
-    let amount_joined = 2;
+    let amount_joined = config.members;
+    let threshold = config.threshold;
 
     let mut state = State::Joining(join_id);
-    swarm.behaviour_mut().gossipsub.publish(global_topic.clone(), serde_json::to_string(&TssMessage::Join(join_id)).unwrap())
-    .unwrap();
     let mut tss_topic : Option<Topic> = None;
+    let mut setup = SessionSetup::default();
+    let mut epoch = 0;
 
-    let (send_channel, receive_channel) = unbounded();
-    let (send_result_channel, receive_result_channel) = unbounded();
+    // Rejoin the scheme we already hold a share of.
+    if let Some(scheme) = keystore.scheme.as_ref() {
+        info!("restored key share for session {} epoch {}, group key {}", scheme.session, scheme.epoch,
+            hex::encode(scheme.keys.group_key().compress().to_bytes()));
+        let topic = Topic::new(config.session_topic(scheme.session));
+        swarm.behaviour_mut().subscribe(&topic);
+        tss_topic = Some(topic);
+        for peer in scheme.members.iter().filter(|peer| **peer != local_peer_id) {
+            swarm.behaviour_mut().kademelia.get_closest_peers(*peer);
+        }
+        swarm.behaviour_mut().tss.restore(Config {
+            session: scheme.session,
+            members: scheme.members.clone(),
+            keys: scheme.keys.clone(),
+        });
+        tss = Some(TssScheme {
+            members: scheme.members.iter().enumerate().map(|(i, peer_id)| TssMember {
+                stellar_key: scheme.stellar_keys.get(i).cloned().unwrap_or_default(),
+                peer_id: *peer_id,
+                index: i as u16 + 1,
+            }).collect(),
+            thresholdkeys: scheme.keys.clone(),
+            epoch: scheme.epoch,
+        });
+        epoch = scheme.epoch;
+        state = State::Ready_for_signing(scheme.session);
+        // the others tell us if someone else leads by now
+        if let Some(lowest) = scheme.members.iter().min_by_key(|peer| peer.to_base58()) {
+            setup.leadership = Leadership::new(*lowest);
+        }
+    }
 
-    let mut key_gen_scope = thread::spawn(||{
-        KeyGenProcess(receive_channel, send_result_channel);
+    // Every line on stdin is a message for the session to sign. Other
+    // members' requests are only co-signed for messages from there too.
+    let mut approved: HashSet<Vec<u8>> = HashSet::new();
+    let (send_sign_request, mut sign_requests) = mpsc::unbounded();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else { break };
+            if send_sign_request.unbounded_send(line.into_bytes()).is_err() {
+                break;
+            }
+        }
+    });
+
+    // Gossipsub drops what arrives at a relay before its mesh has formed, so
+    // we announce ourselves until the key is generated. The same tick drives
+    // the heartbeats.
+    let (send_resend_tick, mut resend_ticks) = mpsc::unbounded();
+    async_std::task::spawn(async move {
+        loop {
+            async_std::task::sleep(RESEND_INTERVAL).await;
+            if send_resend_tick.unbounded_send(()).is_err() {
+                break;
+            }
+        }
     });
 
     loop {
-        match swarm.select_next_some().await {
-            SwarmEvent::NewListenAddr { address, .. } => println!("Listening on {:?}", address),
+        select! {
+            event = swarm.select_next_some() => match event {
+            SwarmEvent::NewListenAddr { address, .. } => info!("Listening on {:?}", address),
             // SwarmEvent::Behaviour(Event::Identify(identity)) => match identity.as_ref() {
             //     libp2p::identify::Event::Received { peer_id, info } => {
             //         if is_bootstrap_node{
@@ -129,43 +305,437 @@ async fn main() -> Result<(), Box<dyn Error>> {
             //     }
             //     _ => {}
             // },
-            SwarmEvent::Behaviour(Event::Gossipsub(gossip_event)) =>{
-                if is_bootstrap_node{
+            // Kademlia only learns the addresses of peers it dialed itself, so
+            // hand it what identify tells us. That's how members find each
+            // other for the tss protocol.
+            SwarmEvent::Behaviour(Event::Identify(event)) => {
+                if let identify::Event::Received { peer_id, info } = *event {
+                    for address in info.listen_addrs {
+                        swarm.behaviour_mut().kademelia.add_address(&peer_id, address);
+                    }
+                }
+            },
+            SwarmEvent::Behaviour(Event::Tss(event)) => match event {
+                // the event has the id of the DKG attempt, the keystore wants
+                // the session the members know it by
+                TssEvent::KeyGenerated { group_key, .. } => {
+                    let (State::KeyGenStep1(session), Some(config), Some(list)) = (state, swarm.behaviour().tss.config(), setup.members.take()) else {
+                        continue;
+                    };
+                    let stellar_keys = list.stellar_keys();
+                    let members = config.members.iter().zip(&stellar_keys).enumerate().map(|(i, (peer_id, stellar_key))| TssMember {
+                        stellar_key: stellar_key.clone(),
+                        peer_id: *peer_id,
+                        index: i as u16 + 1,
+                    }).collect();
+                    let thresholdkeys = config.keys.clone();
+                    info!("key generation done for session {}, group key {}", session, hex::encode(group_key));
+                    epoch += 1;
+                    keystore.scheme = Some(StoredScheme {
+                        session,
+                        epoch,
+                        members: config.members.clone(),
+                        stellar_keys,
+                        keys: thresholdkeys.clone(),
+                    });
+                    save_keystore(&keystore, &keystore_path, passphrase.as_deref());
+                    tss = Some(TssScheme { members, thresholdkeys, epoch });
+                    state = State::Ready_for_signing(session);
+                },
+                // The session reruns the DKG without whoever is to blame, as
+                // long as enough members are left to reach the threshold.
+                // An accusation is settled the same way everywhere, so the
+                // faulty are kept out for good. Who timed out is only what
+                // we saw, they just stay off our next list. If it's us who
+                // lost touch the leader's list won't match and we move on.
+                TssEvent::KeyGenFailed { faulty, unresponsive, .. } => {
+                    let State::KeyGenStep1(session) = state else {
+                        continue;
+                    };
+                    let blamed: Vec<PeerId> = faulty.iter().chain(&unresponsive).cloned().collect();
+                    let left = setup.size(amount_joined).saturating_sub(blamed.len() as u16);
+                    if blamed.is_empty() || blamed.contains(&local_peer_id) || left < threshold.max(2) {
+                        leave_session(&mut swarm, &mut sealer, &mut setup, &mut tss_topic, &mut state, &mut join_id, &global_topic);
+                        continue;
+                    }
+                    info!("restarting session {} with {} members, without {:?}", session, left, blamed);
+                    for peer in faulty {
+                        setup.joined.remove(&peer);
+                        setup.leadership.depose(peer);
+                    }
+                    setup.dropped.extend(blamed);
+                    restart_member_list(&mut swarm, &mut setup, &mut state);
+                },
+                TssEvent::SignRequested { request, from, message } => {
+                    if approved.contains(&message) {
+                        info!("co-signing request {} from {:?}", request, from);
+                        swarm.behaviour_mut().tss.approve_sign(request);
+                    } else {
+                        info!("request {} from {:?} waits for the same message on stdin: {}", request, from,
+                            String::from_utf8_lossy(&message));
+                    }
+                },
+                TssEvent::SignatureProduced { request, signature } => {
+                    info!("signature for request {}: {}", request, hex::encode(signature));
+                },
+                TssEvent::SessionFailed { id, error } => {
+                    warn!("session {} failed: {:?}", id, error);
+                },
+            },
+            SwarmEvent::Behaviour(Event::Gossipsub(GossipsubEvent::Message { propagation_source, message_id, message })) => {
+                // gossipsub forwards nothing before we've checked it
+                let (parsed, acceptance) = match TssMessage::open(&message, &config, &mut sequences) {
+                    Ok(parsed) => (Some(parsed), MessageAcceptance::Accept),
+                    Err(acceptance) => (None, acceptance),
+                };
+                let _ = swarm.behaviour_mut().gossipsub.report_message_validation_result(&message_id, &propagation_source, acceptance);
+                let (Some(parsed), Some(source)) = (parsed, message.source) else {
+                    continue;
+                };
+                debug!("received {:?} from gossipsub from {:?}", parsed, source);
+                if is_bootstrap_node {
+                    // Members usually only share the bootstrap node, so it
+                    // relays every session topic it hears about.
+                    if let TssMessage::Create { session, .. } = parsed {
+                        swarm.behaviour_mut().subscribe(&Topic::new(config.session_topic(session)));
+                    }
                     continue;
                 }
-                match gossip_event {
-                    GossipsubEvent::Message { propagation_source, message_id, message } =>{
-                        let parsed : TssMessage = serde_json::from_slice(&message.data).unwrap();
-                        match &parsed {
-                            TssMessage::Join(x) if should_be_leader(is_leader)  => {
-                                is_leader = true;
-                                state = State::Joining(*x);
-                                swarm.behaviour_mut().gossipsub.publish(global_topic.clone(), serde_json::to_string(&TssMessage::Create(join_id)).unwrap())
-                                    .unwrap();    
-                                tss_topic = Topic::new(format!("tss_session_{}",*x)).into();
-                            },
-                            //todo, the create message should also have the new member count so we know how much to wait for people.
-                            //also use that to calculate id
-                            TssMessage::Create(id) => {
-                                tss_topic = Topic::new(format!("tss_session_{}",*id)).into();
-                                swarm.behaviour_mut().gossipsub.subscribe(tss_topic.as_ref().unwrap()).unwrap();
-                                swarm.behaviour_mut().gossipsub.publish(global_topic.clone(), serde_json::to_string(&TssMessage::Joined(join_id)).unwrap())
-                                    .unwrap();
-                                state = State::Joined(*id);
+                let current = match state {
+                    State::Joining(_) => None,
+                    State::Joined(id) | State::Agreeing(id) | State::KeyGenStep1(id) | State::Ready_for_signing(id) => Some(id),
+                };
+                match parsed {
+                    TssMessage::Create { members, threshold: t, .. } if members != amount_joined || t != threshold => {
+                        debug!("ignoring a session for a {} of {} scheme", t, members);
+                    },
+                    // Of two nodes looking for a session the lower
+                    // peer id creates one.
+                    TssMessage::Join(_) if matches!(state, State::Joining(_)) && precedes(&local_peer_id, &source) => {
+                        state = State::Joined(join_id);
+                        let topic = Topic::new(config.session_topic(join_id));
+                        swarm.behaviour_mut().subscribe(&topic);
+                        tss_topic = Some(topic);
+                        setup.leadership = Leadership::new(local_peer_id);
+                        setup.joined.insert(local_peer_id, config.stellar_key.clone());
+                        info!("created session {}", join_id);
+                        publish(&mut swarm, &mut sealer, &global_topic, &TssMessage::Create { session: join_id, members: amount_joined, threshold });
+                    },
+                    // Several nodes can still end up creating one.
+                    // Everyone moves to the one with the lowest
+                    // leader until a member list is out.
+                    TssMessage::Create { session: id, .. } if matches!(state, State::Joined(current) if current != id)
+                        && setup.members.is_none()
+                        && setup.leadership.leader().map_or(false, |leader| precedes(&source, &leader)) => {
+                        if let Some(topic) = tss_topic.take() {
+                            if let Err(e) = swarm.behaviour_mut().gossipsub.unsubscribe(&topic) {
+                                warn!("couldn't leave the session topic: {:?}", e);
                             }
-                            TssMessage::Joined(id) => {
-                                if get_tss_members_count(tss.as_ref()) == 0{
-                                    state = State::KeyGenStep1(*id);
+                        }
+                        setup = SessionSetup::default();
+                        join_session(&mut swarm, &mut sealer, &mut setup, &mut tss_topic, &mut state, local_peer_id, &config, id, source, Topic::new(config.session_topic(id)));
+                    }
+                    TssMessage::Create { session: id, .. } if matches!(state, State::Joining(_)) => {
+                        join_session(&mut swarm, &mut sealer, &mut setup, &mut tss_topic, &mut state, local_peer_id, &config, id, source, Topic::new(config.session_topic(id)));
+                    }
+                    TssMessage::Joined { session, stellar_key, .. } => {
+                        if current == Some(session) && tss.is_none() && setup.candidates().len() < setup.size(amount_joined) as usize
+                            && !setup.leadership.is_deposed(&source) {
+                            setup.joined.entry(source).or_insert(stellar_key);
+                        }
+                    }
+                    // the rest only counts from those in the session
+                    TssMessage::Members(_) | TssMessage::Accept { .. } | TssMessage::Heartbeat { .. }
+                        if !in_session(&swarm, &setup, &source) => {
+                        debug!("ignoring {:?} from {:?}, who isn't in the session", parsed, source);
+                    },
+                    TssMessage::Members(list) => {
+                        if let State::Joined(session) = state {
+                            if setup.leadership.is_leader(&source) {
+                                let size = setup.size(amount_joined);
+                                match list.validate(session, size, threshold, &local_peer_id, &config.stellar_key) {
+                                    Ok(index) => {
+                                        info!("agreed to session {} as member {} of {}", session, index, size);
+                                        accept_members(&mut swarm, &mut sealer, &mut setup, &mut state, tss_topic.as_ref(), local_peer_id, list);
+                                    },
+                                    Err(e) => {
+                                        warn!("rejecting the members of session {}: {}", session, e);
+                                        leave_session(&mut swarm, &mut sealer, &mut setup, &mut tss_topic, &mut state, &mut join_id, &global_topic);
+                                    },
                                 }
                             }
-                            _ => {}
                         }
-                        println!("received {:?} from gossipsub from {:?}", parsed, message.source);
-                    },
+                    }
+                    TssMessage::Accept { session, digest } => {
+                        if let (State::Joined(id) | State::Agreeing(id), true) = (state, current == Some(session)) {
+                            debug!("{:?} accepted the members of session {}", source, id);
+                            setup.accepts.insert(source, digest);
+                        }
+                    }
+                    TssMessage::Heartbeat { session, term, leader, .. } => {
+                        if current != Some(session) {
+                            continue;
+                        }
+                        setup.leadership.heard_from(source);
+                        let Ok(leader) = leader.parse::<PeerId>() else {
+                            continue;
+                        };
+                        if !in_session(&swarm, &setup, &leader) {
+                            continue;
+                        }
+                        let members = leadership_members(&swarm, &setup, tss.is_some());
+                        if let Some(previous) = setup.leadership.observe(source, term, leader, local_peer_id, &members) {
+                            info!("{:?} leads session {} in term {}", leader, session, term);
+                            drop_leader(&mut swarm, &mut setup, &mut state, tss.is_some(), previous);
+                        }
+                    }
                     _ => {}
                 }
             },
-            e => {println!("{:?}",e);}
+            // Publishing fails until someone else is on the topic, so
+            // (re)announce ourselves whenever a peer subscribes.
+            SwarmEvent::Behaviour(Event::Gossipsub(GossipsubEvent::Subscribed { topic, .. })) if !is_bootstrap_node => {
+                let ours = topic == global_topic.hash() || tss_topic.as_ref().map(|session_topic| session_topic.hash()) == Some(topic);
+                if ours && tss.is_none() {
+                    announce(&mut swarm, &mut sealer, &global_topic, tss_topic.as_ref(), &state, &setup, local_peer_id, &config);
+                }
+            },
+            // A member that restarted listens somewhere else now, ask the
+            // network again instead of retrying the address we remember.
+            SwarmEvent::OutgoingConnectionError { peer_id: Some(peer), error } => {
+                debug!("dialing {:?} failed: {}", peer, error);
+                let is_member = swarm.behaviour().tss.config()
+                    .map_or(false, |config| config.members.contains(&peer));
+                if is_member {
+                    if let DialError::Transport(failed) = error {
+                        for (mut address, _) in failed {
+                            if let Some(Protocol::P2p(_)) = address.iter().last() {
+                                address.pop();
+                            }
+                            swarm.behaviour_mut().kademelia.remove_address(&peer, &address);
+                        }
+                    }
+                    swarm.behaviour_mut().kademelia.get_closest_peers(peer);
+                }
+            },
+            e => debug!("{:?}", e),
+            },
+            _ = resend_ticks.select_next_some() => {
+                if let (State::Joined(session) | State::Agreeing(session) | State::KeyGenStep1(session) | State::Ready_for_signing(session), Some(topic)) = (state, tss_topic.clone()) {
+                    let members = leadership_members(&swarm, &setup, tss.is_some());
+                    if let Some(previous) = setup.leadership.check(local_peer_id, &members) {
+                        info!("{:?} went quiet, {:?} leads session {} in term {}", previous, setup.leadership.leader(),
+                            session, setup.leadership.term());
+                        drop_leader(&mut swarm, &mut setup, &mut state, tss.is_some(), previous);
+                    }
+                    if let Some(leader) = setup.leadership.leader() {
+                        publish(&mut swarm, &mut sealer, &topic, &TssMessage::Heartbeat { session, term: setup.leadership.term(), leader: leader.to_base58() });
+                    }
+                }
+                if tss.is_none() && !is_bootstrap_node {
+                    announce(&mut swarm, &mut sealer, &global_topic, tss_topic.as_ref(), &state, &setup, local_peer_id, &config);
+                }
+            },
+            data = sign_requests.select_next_some() => {
+                approved.insert(data.clone());
+                // someone asked first, join their request instead of starting another
+                let requests = swarm.behaviour().tss.awaiting_approval(&data);
+                if !requests.is_empty() {
+                    for request in requests {
+                        info!("co-signing request {}", request);
+                        swarm.behaviour_mut().tss.approve_sign(request);
+                    }
+                    continue;
+                }
+                let alive: Vec<PeerId> = swarm.behaviour().tss.config()
+                    .map(|config| config.members.iter().filter(|peer| setup.leadership.is_alive(peer)).cloned().collect())
+                    .unwrap_or_default();
+                match swarm.behaviour_mut().tss.sign(data, &alive) {
+                    Ok(request) => info!("signing as request {}", request),
+                    Err(e) => warn!("can't sign: {}", e),
+                }
+            },
+        }
+
+        // The leader publishes the member list once the session is full.
+        if let State::Joined(session) = state {
+            let size = setup.size(amount_joined);
+            if setup.leadership.is_leader(&local_peer_id) && setup.candidates().len() == size as usize {
+                let list = MemberList::new(session, threshold, setup.candidates());
+                match list.validate(session, size, threshold, &local_peer_id, &config.stellar_key) {
+                    Ok(index) => {
+                        info!("publishing the members of session {}, we're member {} of {}", session, index, size);
+                        if let Some(topic) = tss_topic.clone() {
+                            publish(&mut swarm, &mut sealer, &topic, &TssMessage::Members(list.clone()));
+                        }
+                        accept_members(&mut swarm, &mut sealer, &mut setup, &mut state, tss_topic.as_ref(), local_peer_id, list);
+                    },
+                    // only if someone joined with an unparsable peer id
+                    Err(e) => {
+                        warn!("can't use the members of session {}: {}", session, e);
+                        leave_session(&mut swarm, &mut sealer, &mut setup, &mut tss_topic, &mut state, &mut join_id, &global_topic);
+                    },
+                }
+            }
+        }
+        // Everyone on the list has to accept the same list before the DKG.
+        if let (State::Agreeing(session), Some(list)) = (state, setup.members.as_ref()) {
+            let digest = list.digest();
+            let peers = list.peers().unwrap_or_default();
+            let others: Vec<PeerId> = peers.iter().filter(|peer| **peer != local_peer_id).cloned().collect();
+            if let Some(peer) = others.iter().find(|peer| setup.accepts.get(*peer).map_or(false, |theirs| *theirs != digest)) {
+                warn!("{:?} disagrees on the members of session {}", peer, session);
+                leave_session(&mut swarm, &mut sealer, &mut setup, &mut tss_topic, &mut state, &mut join_id, &global_topic);
+            } else if others.iter().all(|peer| setup.accepts.contains_key(peer)) {
+                // look the other members up so they can be dialed
+                for peer in &others {
+                    swarm.behaviour_mut().kademelia.get_closest_peers(*peer);
+                }
+                let (key_gen_id, threshold) = (list.key_gen_id(), list.threshold);
+                swarm.behaviour_mut().tss.start_key_gen(key_gen_id, peers, threshold, config.key_gen_context(session));
+                state = State::KeyGenStep1(session);
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn join_session(swarm: &mut Swarm<Behaviour>, sealer: &mut Sealer, setup: &mut SessionSetup,
+    tss_topic: &mut Option<Topic>, state: &mut State, local_peer_id: PeerId, config: &NodeConfig,
+    id: u64, leader: PeerId, topic: Topic
+) {
+    swarm.behaviour_mut().subscribe(&topic);
+    setup.leadership = Leadership::new(leader);
+    setup.joined.insert(local_peer_id, config.stellar_key.clone());
+    if let Some(joined) = joined(config, local_peer_id, id) {
+        publish(swarm, sealer, &topic, &joined);
+    }
+    *tss_topic = Some(topic);
+    *state = State::Joined(id);
+}
+
+// Tells the session we joined it with our Stellar account. The config
+// requires one on every node but the bootstrap node, which never joins.
+fn joined(config: &NodeConfig, local_peer_id: PeerId, session: u64) -> Option<TssMessage> {
+    let Some(account) = config.stellar_account.as_ref() else {
+        error!("can't join session {} without a Stellar account", session);
+        return None;
+    };
+    Some(TssMessage::Joined { session, stellar_key: config.stellar_key.clone(), signature: account.sign_joined(&local_peer_id, session) })
+}
+
+// Takes our index from `list` and tells the others we're fine with it.
+fn accept_members(swarm: &mut Swarm<Behaviour>, sealer: &mut Sealer, setup: &mut SessionSetup, state: &mut State,
+    tss_topic: Option<&Topic>, local_peer_id: PeerId, list: MemberList
+) {
+    let session = list.session;
+    let digest = list.digest();
+    swarm.behaviour_mut().tss.expect_key_gen(list.key_gen_id(), list.peers().unwrap_or_default());
+    setup.accepts.insert(local_peer_id, digest.clone());
+    setup.members = Some(list);
+    *state = State::Agreeing(session);
+    if let Some(topic) = tss_topic {
+        publish(swarm, sealer, topic, &TssMessage::Accept { session, digest });
+    }
+}
+
+// Gives up on the session being set up and looks for another one.
+fn leave_session(swarm: &mut Swarm<Behaviour>, sealer: &mut Sealer, setup: &mut SessionSetup, tss_topic: &mut Option<Topic>,
+    state: &mut State, join_id: &mut u64, global_topic: &Topic
+) {
+    if let Some(topic) = tss_topic.take() {
+        if let Err(e) = swarm.behaviour_mut().gossipsub.unsubscribe(&topic) {
+            warn!("couldn't leave the session topic: {:?}", e);
         }
     }
+    swarm.behaviour_mut().tss.cancel_key_gen();
+    *setup = SessionSetup::default();
+    // a fresh id, so the next session doesn't reuse the old topic
+    *join_id = rand::random();
+    *state = State::Joining(*join_id);
+    publish(swarm, sealer, global_topic, &TssMessage::Join(*join_id));
+}
+
+// Only those who joined the session, are on its member list or hold a
+// share of its key have a say in it.
+fn in_session(swarm: &Swarm<Behaviour>, setup: &SessionSetup, peer: &PeerId) -> bool {
+    setup.joined.contains_key(peer)
+        || setup.members.as_ref().and_then(|list| list.peers().ok()).map_or(false, |peers| peers.contains(peer))
+        || swarm.behaviour().tss.config().map_or(false, |config| config.members.contains(peer))
+}
+
+// Who can lead the session: the key's members once there is one, everyone
+// who joined until then.
+fn leadership_members(swarm: &Swarm<Behaviour>, setup: &SessionSetup, has_key: bool) -> Vec<PeerId> {
+    match swarm.behaviour().tss.config() {
+        Some(scheme) if has_key => scheme.members.clone(),
+        _ => setup.joined.keys().cloned().collect(),
+    }
+}
+
+// The session carries on without `previous` as its leader. Whatever was
+// agreed with them on the list is redone, what the others said in the
+// meantime is kept. Unlike members blamed by a failed DKG they still count
+// towards the list, a new node has to join in their place.
+fn drop_leader(swarm: &mut Swarm<Behaviour>, setup: &mut SessionSetup, state: &mut State, has_key: bool, previous: PeerId) {
+    if has_key {
+        return;
+    }
+    setup.joined.remove(&previous);
+    if let State::Agreeing(session) | State::KeyGenStep1(session) = *state {
+        let on_list = setup.members.as_ref()
+            .map_or(false, |list| list.members.iter().any(|member| member.peer_id == previous.to_base58()));
+        if on_list {
+            info!("restarting the member list of session {} without {:?}", session, previous);
+            restart_member_list(swarm, setup, state);
+        }
+    }
+}
+
+// Back to collecting members, the leader publishes a new list once the
+// session is full again.
+fn restart_member_list(swarm: &mut Swarm<Behaviour>, setup: &mut SessionSetup, state: &mut State) {
+    swarm.behaviour_mut().tss.cancel_key_gen();
+    setup.members = None;
+    setup.accepts.clear();
+    if let State::Agreeing(session) | State::KeyGenStep1(session) = *state {
+        *state = State::Joined(session);
+    }
+}
+
+// Gossipsub drops what arrives before the mesh has formed, so whatever the
+// others are still waiting for from us gets published again.
+#[allow(clippy::too_many_arguments)]
+fn announce(swarm: &mut Swarm<Behaviour>, sealer: &mut Sealer, global_topic: &Topic, tss_topic: Option<&Topic>, state: &State,
+    setup: &SessionSetup, local_peer_id: PeerId, config: &NodeConfig
+) {
+    if let State::Joining(join_id) = *state {
+        publish(swarm, sealer, global_topic, &TssMessage::Join(join_id));
+        return;
+    }
+    let Some(topic) = tss_topic else {
+        return;
+    };
+    let is_leader = setup.leadership.is_leader(&local_peer_id);
+    match *state {
+        State::Joined(session) => {
+            if let Some(joined) = joined(config, local_peer_id, session) {
+                publish(swarm, sealer, topic, &joined);
+            }
+            // so nodes that are still looking find the session
+            if is_leader {
+                publish(swarm, sealer, global_topic, &TssMessage::Create { session, members: config.members, threshold: config.threshold });
+            }
+        },
+        State::Agreeing(session) | State::KeyGenStep1(session) => {
+            let Some(list) = setup.members.as_ref() else {
+                return;
+            };
+            if is_leader {
+                publish(swarm, sealer, topic, &TssMessage::Members(list.clone()));
+            }
+            publish(swarm, sealer, topic, &TssMessage::Accept { session, digest: list.digest() });
+        },
+        _ => {}
+    }
 }
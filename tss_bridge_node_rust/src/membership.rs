@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    // base58, like everywhere else peer ids are shown
+    pub peer_id: String,
+    pub stellar_key: String,
+}
+
+// Who takes part in a DKG session. The leader publishes it once enough
+// nodes joined and everyone derives its participant index from it, so all
+// members have to end up with the exact same list.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MemberList {
+    pub session: u64,
+    pub threshold: u16,
+    // sorted by peer id, participant i is at i - 1
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug)]
+pub enum MembershipError {
+    WrongSession(u64),
+    WrongSize(usize),
+    WrongThreshold(u16),
+    Unsorted,
+    BadPeerId(String),
+    // the list doesn't have us, or has us under another Stellar key
+    NotAMember,
+    StellarKeyMismatch,
+}
+
+impl fmt::Display for MembershipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MembershipError::WrongSession(session) => write!(f, "list is for session {}", session),
+            MembershipError::WrongSize(n) => write!(f, "list has {} members", n),
+            MembershipError::WrongThreshold(t) => write!(f, "list asks for a threshold of {}", t),
+            MembershipError::Unsorted => write!(f, "members aren't sorted or appear twice"),
+            MembershipError::BadPeerId(peer) => write!(f, "invalid peer id {}", peer),
+            MembershipError::NotAMember => write!(f, "we aren't on the list"),
+            MembershipError::StellarKeyMismatch => write!(f, "list has another Stellar key for us"),
+        }
+    }
+}
+
+impl MemberList {
+    pub fn new(session: u64, threshold: u16, members: impl IntoIterator<Item = (PeerId, String)>) -> Self {
+        let mut members: Vec<Member> = members.into_iter()
+            .map(|(peer_id, stellar_key)| Member { peer_id: peer_id.to_base58(), stellar_key })
+            .collect();
+        members.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        MemberList { session, threshold, members }
+    }
+
+    // Checks the list is the one we expect to run the DKG with and returns
+    // our participant index.
+    pub fn validate(
+        &self,
+        session: u64,
+        n: u16,
+        threshold: u16,
+        local_peer_id: &PeerId,
+        stellar_key: &str,
+    ) -> Result<u16, MembershipError> {
+        if self.session != session {
+            return Err(MembershipError::WrongSession(self.session));
+        }
+        if self.members.len() != n as usize {
+            return Err(MembershipError::WrongSize(self.members.len()));
+        }
+        if self.threshold != threshold {
+            return Err(MembershipError::WrongThreshold(self.threshold));
+        }
+        // strictly increasing also rules out duplicates
+        if self.members.windows(2).any(|pair| pair[0].peer_id >= pair[1].peer_id) {
+            return Err(MembershipError::Unsorted);
+        }
+        let peers = self.peers()?;
+        let position = peers.iter().position(|peer| peer == local_peer_id).ok_or(MembershipError::NotAMember)?;
+        if self.members[position].stellar_key != stellar_key {
+            return Err(MembershipError::StellarKeyMismatch);
+        }
+        Ok(position as u16 + 1)
+    }
+
+    // In participant index order.
+    pub fn peers(&self) -> Result<Vec<PeerId>, MembershipError> {
+        let peers: Vec<PeerId> = self.members.iter()
+            .map(|member| member.peer_id.parse().map_err(|_| MembershipError::BadPeerId(member.peer_id.clone())))
+            .collect::<Result<_, _>>()?;
+        // two spellings of the same peer id would sort apart
+        if peers.iter().collect::<HashSet<_>>().len() != peers.len() {
+            return Err(MembershipError::Unsorted);
+        }
+        Ok(peers)
+    }
+
+    pub fn stellar_keys(&self) -> Vec<String> {
+        self.members.iter().map(|member| member.stellar_key.clone()).collect()
+    }
+
+    // What members compare to make sure they agree on the list.
+    pub fn digest(&self) -> String {
+        hex::encode(self.hash())
+    }
+
+    // Every attempt at a DKG in a session gets its own id, so messages of an
+    // abandoned attempt can't end up in the next one.
+    pub fn key_gen_id(&self) -> u64 {
+        let hash = self.hash();
+        u64::from_le_bytes(hash[..8].try_into().unwrap())
+    }
+
+    fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.session.to_le_bytes());
+        hasher.update(self.threshold.to_le_bytes());
+        for member in &self.members {
+            for field in [&member.peer_id, &member.stellar_key] {
+                hasher.update((field.len() as u32).to_le_bytes());
+                hasher.update(field.as_bytes());
+            }
+        }
+        hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peers(n: usize) -> Vec<(PeerId, String)> {
+        (0..n).map(|i| (PeerId::random(), format!("GSTELLAR{}", i))).collect()
+    }
+
+    #[test]
+    fn validate_returns_our_index() {
+        let joined = peers(3);
+        let list = MemberList::new(7, 2, joined.clone());
+        for (peer, stellar_key) in &joined {
+            let index = list.validate(7, 3, 2, peer, stellar_key).unwrap();
+            assert_eq!(list.peers().unwrap()[index as usize - 1], *peer);
+        }
+    }
+
+    #[test]
+    fn validate_rejects_other_lists() {
+        let joined = peers(3);
+        let (us, stellar_key) = joined[0].clone();
+        let list = MemberList::new(7, 2, joined.clone());
+        assert!(matches!(list.validate(8, 3, 2, &us, &stellar_key), Err(MembershipError::WrongSession(7))));
+        assert!(matches!(list.validate(7, 4, 2, &us, &stellar_key), Err(MembershipError::WrongSize(3))));
+        assert!(matches!(list.validate(7, 3, 3, &us, &stellar_key), Err(MembershipError::WrongThreshold(2))));
+        assert!(matches!(list.validate(7, 3, 2, &PeerId::random(), &stellar_key), Err(MembershipError::NotAMember)));
+        assert!(matches!(list.validate(7, 3, 2, &us, "GSOMEONEELSE"), Err(MembershipError::StellarKeyMismatch)));
+
+        let mut unsorted = list.clone();
+        unsorted.members.swap(0, 1);
+        assert!(matches!(unsorted.validate(7, 3, 2, &us, &stellar_key), Err(MembershipError::Unsorted)));
+
+        let mut twice = list.clone();
+        twice.members[1] = twice.members[0].clone();
+        assert!(matches!(twice.validate(7, 3, 2, &us, &stellar_key), Err(MembershipError::Unsorted)));
+
+        let mut bad_peer = list;
+        bad_peer.members[2].peer_id = "zzzz".to_string();
+        assert!(matches!(bad_peer.validate(7, 3, 2, &us, &stellar_key), Err(MembershipError::BadPeerId(_))));
+    }
+
+    #[test]
+    fn members_agree_on_the_same_list_only() {
+        let joined = peers(3);
+        let list = MemberList::new(7, 2, joined.clone());
+        // the order members joined in doesn't matter
+        let reversed = MemberList::new(7, 2, joined.iter().rev().cloned());
+        assert_eq!(list, reversed);
+        assert_eq!(list.digest(), reversed.digest());
+        assert_eq!(list.key_gen_id(), reversed.key_gen_id());
+
+        let mut other_key = joined.clone();
+        other_key[1].1 = "GSOMEONEELSE".to_string();
+        let mut replaced = joined.clone();
+        replaced[2].0 = PeerId::random();
+        for other in [
+            MemberList::new(8, 2, joined.clone()),
+            MemberList::new(7, 3, joined.clone()),
+            MemberList::new(7, 2, other_key),
+            MemberList::new(7, 2, replaced),
+            MemberList::new(7, 2, joined[..2].to_vec()),
+        ] {
+            assert_ne!(list.digest(), other.digest());
+            assert_ne!(list.key_gen_id(), other.key_gen_id());
+        }
+    }
+}
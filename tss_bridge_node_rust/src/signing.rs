@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use futures::channel::mpsc::UnboundedSender;
+use libp2p::identity::ed25519;
+use modular_frost::{
+    algorithm::Schnorr,
+    curve::{Ed25519, IetfEd25519Hram},
+    sign::{AlgorithmMachine, PreprocessMachine, SignMachine, SignatureMachine, Writable},
+    FrostError, ThresholdKeys,
+};
+use rand::rngs::OsRng;
+
+// how long each round waits for the other signers
+const ROUND_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub enum SignThreadMessage {
+    // preprocess from another signer
+    Preprocess(u16, Vec<u8>),
+    // signature share from another signer
+    Share(u16, Vec<u8>),
+}
+
+pub enum SignThreadOutput {
+    // our preprocess for the other signers
+    Preprocess(u64, Vec<u8>),
+    // our signature share for the other signers
+    Share(u64, Vec<u8>),
+    Signature(u64, [u8; 64]),
+    Failed(u64, SignError),
+}
+
+#[derive(Debug)]
+pub enum SignError {
+    // we can't sign before key generation finished
+    NoKeys,
+    // the session needs exactly threshold signers, us included
+    InvalidSigningSet,
+    // a signer sent bytes we couldn't parse
+    Malformed(u16),
+    // these signers didn't send their part of a round in time
+    Timeout(Vec<u16>),
+    // the session was dropped before it finished
+    Cancelled,
+    Frost(FrostError),
+}
+
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignError::NoKeys => write!(f, "no threshold keys yet"),
+            SignError::InvalidSigningSet => write!(f, "invalid signing set"),
+            SignError::Malformed(i) => write!(f, "malformed message from signer {}", i),
+            SignError::Timeout(signers) => write!(f, "timed out waiting for signers {:?}", signers),
+            SignError::Cancelled => write!(f, "cancelled"),
+            SignError::Frost(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<FrostError> for SignError {
+    fn from(e: FrostError) -> Self {
+        SignError::Frost(e)
+    }
+}
+
+// The requester `own` and the first `threshold - 1` of the other given
+// member indices. The requester picks from the members it knows to be
+// alive and sends the set along.
+pub fn choose_signers(mut indices: Vec<u16>, own: u16, threshold: u16) -> Option<Vec<u16>> {
+    indices.retain(|i| *i != own);
+    indices.sort_unstable();
+    indices.dedup();
+    let others = (threshold as usize).checked_sub(1)?;
+    if indices.len() < others {
+        return None;
+    }
+    indices.truncate(others);
+    indices.push(own);
+    indices.sort_unstable();
+    Some(indices)
+}
+
+// Whether a set `requester` picked is one `choose_signers` could have.
+pub fn is_signing_set(signers: &[u16], requester: u16, threshold: u16, n: u16) -> bool {
+    signers.len() == threshold as usize
+        && signers.contains(&requester)
+        && signers.windows(2).all(|pair| pair[0] < pair[1])
+        && signers.iter().all(|i| (1..=n).contains(i))
+}
+
+// Whether `signature` is an Ed25519 signature of `message` under the
+// group key, like the one the signers produce.
+pub fn verify_signature(keys: &ThresholdKeys<Ed25519>, message: &[u8], signature: &[u8; 64]) -> bool {
+    let group_key = keys.group_key().compress().to_bytes();
+    ed25519::PublicKey::decode(&group_key).map_or(false, |key| key.verify(message, signature))
+}
+
+// One signing run for a single message. Owns the SignProcess thread.
+pub struct SignSession {
+    pub signers: Vec<u16>,
+    pub message: Vec<u8>,
+    send_channel: Sender<SignThreadMessage>,
+}
+
+impl SignSession {
+    pub fn start(id: u64, keys: ThresholdKeys<Ed25519>, signers: Vec<u16>, message: Vec<u8>,
+        output: UnboundedSender<SignThreadOutput>
+    ) -> Self {
+        let (send_channel, receive_channel) = unbounded();
+        let (process_signers, process_message) = (signers.clone(), message.clone());
+        thread::spawn(move || {
+            if let Err(e) = SignProcess(id, keys, &process_signers, &process_message, receive_channel, &output) {
+                let _ = output.unbounded_send(SignThreadOutput::Failed(id, e));
+            }
+        });
+        SignSession { signers, message, send_channel }
+    }
+
+    pub fn handle_preprocess(&self, from: u16, data: Vec<u8>) {
+        if self.signers.contains(&from) {
+            let _ = self.send_channel.send(SignThreadMessage::Preprocess(from, data));
+        }
+    }
+
+    pub fn handle_share(&self, from: u16, data: Vec<u8>) {
+        if self.signers.contains(&from) {
+            let _ = self.send_channel.send(SignThreadMessage::Share(from, data));
+        }
+    }
+}
+
+pub fn SignProcess(id: u64, keys: ThresholdKeys<Ed25519>, signers: &[u16], message: &[u8],
+    receive_channel: Receiver<SignThreadMessage>,
+    send_channel: &UnboundedSender<SignThreadOutput>
+) -> Result<(), SignError> {
+    let params = keys.params();
+    if signers.len() != params.t() as usize || !signers.contains(&params.i()) {
+        return Err(SignError::InvalidSigningSet);
+    }
+
+    let machine = AlgorithmMachine::new(Schnorr::<Ed25519, IetfEd25519Hram>::new(), keys, signers)?;
+    let (sign_machine, preprocess) = machine.preprocess(&mut OsRng);
+    send_channel.unbounded_send(SignThreadOutput::Preprocess(id, preprocess.serialize()))
+        .map_err(|_| SignError::Cancelled)?;
+
+    // Shares can only follow a complete set of preprocesses, but gossip
+    // doesn't keep them in order, so they're collected as raw bytes.
+    let others: Vec<u16> = signers.iter().filter(|i| **i != params.i()).cloned().collect();
+    let mut preprocesses = HashMap::new();
+    let mut share_bytes = HashMap::new();
+    let deadline = Instant::now() + ROUND_TIMEOUT;
+    while preprocesses.len() < others.len() {
+        let message = receive_channel.recv_deadline(deadline).map_err(|e| match e {
+            RecvTimeoutError::Timeout => SignError::Timeout(missing(&others, &preprocesses)),
+            RecvTimeoutError::Disconnected => SignError::Cancelled,
+        })?;
+        match message {
+            SignThreadMessage::Preprocess(from, data) => {
+                let preprocess = sign_machine.read_preprocess(&mut &data[..])
+                    .map_err(|_| SignError::Malformed(from))?;
+                preprocesses.insert(from, preprocess);
+            },
+            SignThreadMessage::Share(from, data) => {
+                share_bytes.insert(from, data);
+            },
+        }
+    }
+
+    let (signature_machine, share) = sign_machine.sign(preprocesses, message)?;
+    send_channel.unbounded_send(SignThreadOutput::Share(id, share.serialize()))
+        .map_err(|_| SignError::Cancelled)?;
+
+    let deadline = Instant::now() + ROUND_TIMEOUT;
+    while share_bytes.len() < others.len() {
+        let message = receive_channel.recv_deadline(deadline).map_err(|e| match e {
+            RecvTimeoutError::Timeout => SignError::Timeout(missing(&others, &share_bytes)),
+            RecvTimeoutError::Disconnected => SignError::Cancelled,
+        })?;
+        if let SignThreadMessage::Share(from, data) = message {
+            share_bytes.insert(from, data);
+        }
+    }
+    let mut shares = HashMap::new();
+    for (from, data) in share_bytes {
+        let share = signature_machine.read_share(&mut &data[..]).map_err(|_| SignError::Malformed(from))?;
+        shares.insert(from, share);
+    }
+
+    let signature = signature_machine.complete(shares)?;
+    let mut bytes = [0; 64];
+    bytes.copy_from_slice(&signature.serialize());
+    send_channel.unbounded_send(SignThreadOutput::Signature(id, bytes))
+        .map_err(|_| SignError::Cancelled)
+}
+
+fn missing<T>(others: &[u16], received: &HashMap<u16, T>) -> Vec<u16> {
+    others.iter().filter(|i| !received.contains_key(i)).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choose_signers_takes_the_lowest_others() {
+        assert_eq!(choose_signers(vec![5, 1, 3, 2], 4, 3), Some(vec![1, 2, 4]));
+        assert_eq!(choose_signers(vec![1, 2, 3], 1, 2), Some(vec![1, 2]));
+        // duplicates and the requester itself don't count as others
+        assert_eq!(choose_signers(vec![3, 3, 3, 2], 3, 2), Some(vec![2, 3]));
+    }
+
+    #[test]
+    fn choose_signers_needs_enough_others() {
+        assert_eq!(choose_signers(vec![2, 2], 1, 3), None);
+        assert_eq!(choose_signers(vec![], 1, 2), None);
+        assert_eq!(choose_signers(vec![2, 3], 1, 0), None);
+        assert_eq!(choose_signers(vec![], 1, 1), Some(vec![1]));
+    }
+
+    #[test]
+    fn is_signing_set_accepts_what_choose_signers_picks() {
+        let signers = choose_signers(vec![1, 2, 3, 5], 4, 3).unwrap();
+        assert!(is_signing_set(&signers, 4, 3, 5));
+    }
+
+    #[test]
+    fn is_signing_set_rejects_bad_sets() {
+        // wrong size
+        assert!(!is_signing_set(&[1, 2], 1, 3, 3));
+        assert!(!is_signing_set(&[1, 2, 3], 1, 2, 3));
+        // unsorted or repeated
+        assert!(!is_signing_set(&[2, 1], 1, 2, 3));
+        assert!(!is_signing_set(&[1, 1], 1, 2, 3));
+        // outside 1..=n
+        assert!(!is_signing_set(&[0, 1], 1, 2, 3));
+        assert!(!is_signing_set(&[1, 4], 1, 2, 3));
+        // without the requester
+        assert!(!is_signing_set(&[1, 2], 3, 2, 3));
+    }
+}
@@ -0,0 +1,49 @@
+use libp2p::identity::ed25519::{Keypair, PublicKey, SecretKey};
+use libp2p::PeerId;
+use stellar_strkey::{StrkeyPrivateKeyEd25519, StrkeyPublicKeyEd25519};
+
+// keeps join signatures from being valid as anything else the account signs
+const JOIN_DOMAIN: &[u8] = b"tss_bridge_node_rust/joined/v1";
+
+// The Stellar account a member joins sessions with. Members sign their
+// peer id with it, so nobody can join under someone else's account.
+pub struct StellarAccount {
+    keypair: Keypair,
+}
+
+fn joined_bytes(peer_id: &PeerId, session: u64) -> Vec<u8> {
+    let mut bytes = JOIN_DOMAIN.to_vec();
+    bytes.extend_from_slice(&peer_id.to_bytes());
+    bytes.extend_from_slice(&session.to_le_bytes());
+    bytes
+}
+
+impl StellarAccount {
+    // From the account's secret seed, `S...`.
+    pub fn from_secret(secret: &str) -> Option<Self> {
+        let StrkeyPrivateKeyEd25519(seed) = StrkeyPrivateKeyEd25519::from_string(secret).ok()?;
+        let secret = SecretKey::from_bytes(seed).ok()?;
+        Some(StellarAccount { keypair: Keypair::from(secret) })
+    }
+
+    // The account id, `G...`.
+    pub fn id(&self) -> String {
+        StrkeyPublicKeyEd25519(self.keypair.public().encode()).to_string()
+    }
+
+    // Hex signature binding `peer_id` to the account for `session`.
+    pub fn sign_joined(&self, peer_id: &PeerId, session: u64) -> String {
+        hex::encode(self.keypair.sign(&joined_bytes(peer_id, session)))
+    }
+}
+
+// Checks `peer_id` joined `session` with the account `id`.
+pub fn verify_joined(id: &str, peer_id: &PeerId, session: u64, signature: &str) -> bool {
+    let Ok(StrkeyPublicKeyEd25519(key)) = StrkeyPublicKeyEd25519::from_string(id) else {
+        return false;
+    };
+    let (Ok(key), Ok(signature)) = (PublicKey::decode(&key), hex::decode(signature)) else {
+        return false;
+    };
+    key.verify(&joined_bytes(peer_id, session), &signature)
+}
@@ -1,75 +1,411 @@
-use core::panic;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crossbeam::channel::{Receiver, Sender};
-use modular_frost::{curve::{Ed25519, Ciphersuite}, ThresholdParams, dkg::{frost::{KeyGenMachine, Commitments, KeyMachine, SecretShare}, encryption::{EncryptionKeyMessage, EncryptedMessage}}, ThresholdCore};
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use futures::channel::mpsc::UnboundedSender;
+use modular_frost::{curve::{Ed25519, Ciphersuite}, ThresholdParams, dkg::{frost::{KeyGenMachine, Commitments, SecretShare, FrostError}, encryption::{EncryptionKeyMessage, EncryptedMessage, EncryptionKeyProof}}, ThresholdCore};
 use rand::rngs::OsRng;
 
+type EdSecretShare = SecretShare<<Ed25519 as Ciphersuite>::F>;
+
+// how long each round waits for the other participants
+const ROUND_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub enum KeyGenThreadMessage  {
-    Start(u16,u16,u16),
     // commitment from another user
     Commitment((u16,EncryptionKeyMessage<Ed25519,Commitments<Ed25519>>)),
     // share from another user.
-    SecretShare((u16,EncryptedMessage<Ed25519,SecretShare<<modular_frost::curve::Ed25519 as Ciphersuite>::F>>))
-}   
+    SecretShare((u16,EncryptedMessage<Ed25519,EdSecretShare>)),
+    // another participant's share checked out
+    Confirmation(u16),
+    Accusation(Accusation),
+}
+
+// `recipient` says the share `sender` encrypted for it is invalid. The
+// other participants can tell which of the two is lying.
+pub struct Accusation {
+    pub sender: u16,
+    pub recipient: u16,
+    pub share: EncryptedMessage<Ed25519, EdSecretShare>,
+    pub proof: Option<EncryptionKeyProof<Ed25519>>,
+}
 
 pub enum KeyGenThreadOutput {
     // out commitments for others
     Commitments(EncryptionKeyMessage<Ed25519, Commitments<Ed25519>>),
     // out shares for others.
-    SecretShares(HashMap<u16,EncryptedMessage<Ed25519,SecretShare<<modular_frost::curve::Ed25519 as Ciphersuite>::F>>>),
-    Key(ThresholdCore<Ed25519>)
+    SecretShares(HashMap<u16,EncryptedMessage<Ed25519,EdSecretShare>>),
+    // our share checked out, for the others
+    Confirmation,
+    // the share `participant` sent us is invalid, the others get it and
+    // the proof to check that
+    Accusation { participant: u16, share: Vec<u8>, proof: Option<Vec<u8>> },
+    Key(ThresholdCore<Ed25519>),
+    Failed(KeyGenError),
+}
+
+#[derive(Debug)]
+pub enum KeyGenError {
+    // threshold, participant count and index don't fit together
+    InvalidParams,
+    // the session was dropped before it finished
+    Cancelled,
+    // these participants didn't send their part of a round in time
+    Timeout(Vec<u16>),
+    // `faulty` sent invalid commitments or shares, or accused someone
+    // falsely. `unresponsive` hadn't confirmed or accused when the round
+    // timed out.
+    Faulty { faulty: Vec<u16>, unresponsive: Vec<u16> },
+    Frost(FrostError<Ed25519>),
+}
+
+impl KeyGenError {
+    fn faulty(participant: u16) -> Self {
+        KeyGenError::Faulty { faulty: vec![participant], unresponsive: Vec::new() }
+    }
+
+    // Participants to leave out of the next attempt for what they did.
+    pub fn faulty_participants(&self) -> &[u16] {
+        match self {
+            KeyGenError::Faulty { faulty, .. } => faulty,
+            _ => &[],
+        }
+    }
+
+    // Participants to leave out of the next attempt for not answering.
+    pub fn unresponsive_participants(&self) -> &[u16] {
+        match self {
+            KeyGenError::Timeout(participants) | KeyGenError::Faulty { unresponsive: participants, .. } => participants,
+            _ => &[],
+        }
+    }
+}
+
+impl fmt::Display for KeyGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyGenError::InvalidParams => write!(f, "invalid threshold parameters"),
+            KeyGenError::Cancelled => write!(f, "cancelled"),
+            KeyGenError::Timeout(participants) => write!(f, "timed out waiting for {:?}", participants),
+            KeyGenError::Faulty { faulty, unresponsive } if unresponsive.is_empty() => write!(f, "{:?} misbehaved", faulty),
+            KeyGenError::Faulty { faulty, unresponsive } => {
+                write!(f, "{:?} misbehaved, timed out waiting for {:?}", faulty, unresponsive)
+            },
+            KeyGenError::Frost(e) => write!(f, "{:?}", e),
+        }
+    }
+}
+
+impl From<FrostError<Ed25519>> for KeyGenError {
+    fn from(e: FrostError<Ed25519>) -> Self {
+        match e {
+            FrostError::InvalidCommitments(participant) | FrostError::InvalidShare { participant, .. } => {
+                KeyGenError::faulty(participant)
+            },
+            e => KeyGenError::Frost(e),
+        }
+    }
 }
 
-pub fn KeyGenProcess(receive_channel: Receiver<KeyGenThreadMessage>,
-    send_channel: Sender<KeyGenThreadOutput>
+// One DKG run. Owns the KeyGenProcess thread and turns the bytes peers send
+// over gossipsub into messages for it.
+pub struct KeyGenSession {
+    pub params: ThresholdParams,
+    send_channel: Sender<KeyGenThreadMessage>,
+}
+
+impl KeyGenSession {
+    // `context` has to be the same for every member, it ends up in the key.
+    pub fn start(context: String, threshold: u16, n: u16, index: u16, output: UnboundedSender<KeyGenThreadOutput>
+    ) -> Result<Self, KeyGenError> {
+        let params = ThresholdParams::new(threshold, n, index).map_err(|_| KeyGenError::InvalidParams)?;
+        Ok(Self::spawn(context, params, ROUND_TIMEOUT, output))
+    }
+
+    fn spawn(context: String, params: ThresholdParams, round_timeout: Duration, output: UnboundedSender<KeyGenThreadOutput>) -> Self {
+        let (send_channel, receive_channel) = unbounded();
+        thread::spawn(move || {
+            KeyGenProcess(context, params, round_timeout, receive_channel, output);
+        });
+        KeyGenSession { params, send_channel }
+    }
+
+    // The thread is gone once the run failed, what's still arriving for it
+    // is dropped.
+    pub fn handle_commitments(&self, from: u16, data: &[u8]) -> io::Result<()> {
+        let commitments = EncryptionKeyMessage::read(&mut &data[..], self.params)?;
+        let _ = self.send_channel.send(KeyGenThreadMessage::Commitment((from, commitments)));
+        Ok(())
+    }
+
+    pub fn handle_share(&self, from: u16, data: &[u8]) -> io::Result<()> {
+        let share = EncryptedMessage::read(&mut &data[..], self.params)?;
+        let _ = self.send_channel.send(KeyGenThreadMessage::SecretShare((from, share)));
+        Ok(())
+    }
+
+    pub fn handle_confirmation(&self, from: u16) {
+        let _ = self.send_channel.send(KeyGenThreadMessage::Confirmation(from));
+    }
+
+    // `from` accuses `accused` of sending it an invalid share.
+    pub fn handle_accusation(&self, from: u16, accused: u16, share: &[u8], proof: Option<&[u8]>) -> io::Result<()> {
+        let share = EncryptedMessage::read(&mut &share[..], self.params)?;
+        let proof = proof.map(|mut proof| EncryptionKeyProof::read(&mut proof)).transpose()?;
+        let accusation = Accusation { sender: accused, recipient: from, share, proof };
+        let _ = self.send_channel.send(KeyGenThreadMessage::Accusation(accusation));
+        Ok(())
+    }
+}
+
+pub fn KeyGenProcess(context: String, params: ThresholdParams, round_timeout: Duration,
+    receive_channel: Receiver<KeyGenThreadMessage>, send_channel: UnboundedSender<KeyGenThreadOutput>
 ){
-    let mut start_msg = receive_channel.recv().unwrap();
-    let (threshold, n, index) = match start_msg {
-        KeyGenThreadMessage::Start(t ,n, i) => {(t,n,i)},
-        _ => {panic!("invalid msg")}
+    let output = match run_key_gen(context, params, round_timeout, &receive_channel, &send_channel) {
+        Ok(key) => KeyGenThreadOutput::Key(key),
+        Err(e) => KeyGenThreadOutput::Failed(e),
     };
-    
-    let key_params = ThresholdParams::new(threshold,n,index ).unwrap();
+    let _ = send_channel.unbounded_send(output);
+}
+
+// the session dropping its end means the run was cancelled
+fn output(send_channel: &UnboundedSender<KeyGenThreadOutput>, output: KeyGenThreadOutput) -> Result<(), KeyGenError> {
+    send_channel.unbounded_send(output).map_err(|_| KeyGenError::Cancelled)
+}
 
-    let key_gen_machine = KeyGenMachine::<Ed25519>::new(key_params.clone(),"yeet".into());
+fn run_key_gen(context: String, params: ThresholdParams, round_timeout: Duration,
+    receive_channel: &Receiver<KeyGenThreadMessage>, send_channel: &UnboundedSender<KeyGenThreadOutput>
+) -> Result<ThresholdCore<Ed25519>, KeyGenError> {
+    let key_gen_machine = KeyGenMachine::<Ed25519>::new(params, context);
+    let others: Vec<u16> = (1..=params.n()).filter(|i| *i != params.i()).collect();
+    let mut inbox = Inbox::new(round_timeout);
 
     let (secret_share_machine, commitments) = key_gen_machine.generate_coefficients(&mut OsRng);
+    output(send_channel, KeyGenThreadOutput::Commitments(commitments))?;
+    inbox.wait_for(receive_channel, &others, |inbox, i| inbox.commitments.contains_key(&i))?;
 
-    send_channel.send(KeyGenThreadOutput::Commitments(commitments));
+    let commitments = std::mem::take(&mut inbox.commitments);
+    let (key_machine, shares) = secret_share_machine.generate_secret_shares(&mut OsRng, commitments)?;
+    output(send_channel, KeyGenThreadOutput::SecretShares(shares))?;
+    inbox.wait_for(receive_channel, &others, |inbox, i| inbox.shares.contains_key(&i))?;
 
-    let mut commitments_map = HashMap::<u16,EncryptionKeyMessage<Ed25519,Commitments<Ed25519>>>::new();
-    loop {
-        let (index, commitment) = match receive_channel.recv().unwrap() {
-            KeyGenThreadMessage::Commitment((index,commitment)) => {
-                (index,commitment)
-            },
-            _ => panic!("invalid msg")
-        };
-        commitments_map.insert(index, commitment);
-        if commitments_map.len() == (n - 1) as usize{
-            break;
+    // kept to back up an accusation
+    let mut received: HashMap<u16, Vec<u8>> = inbox.shares.iter().map(|(i, share)| (*i, share.serialize())).collect();
+    let blame_machine = match key_machine.calculate_share(&mut OsRng, std::mem::take(&mut inbox.shares)) {
+        Ok(blame_machine) => blame_machine,
+        Err(FrostError::InvalidShare { participant, blame }) => {
+            let share = received.remove(&participant).unwrap_or_default();
+            let proof = blame.map(|proof| proof.serialize());
+            output(send_channel, KeyGenThreadOutput::Accusation { participant, share, proof })?;
+            return Err(KeyGenError::faulty(participant));
+        },
+        Err(e) => return Err(e.into()),
+    };
+
+    // Nobody uses the key before every share checked out. Accusations end
+    // the run instead, with whoever they prove wrong to blame. Everyone gets
+    // to confirm or accuse first, so one restart leaves out every fault.
+    output(send_channel, KeyGenThreadOutput::Confirmation)?;
+    let unresponsive = match inbox.wait_for(receive_channel, &others, |inbox, i| {
+        inbox.confirmations.contains(&i) || inbox.accusations.iter().any(|accusation| accusation.recipient == i)
+    }) {
+        Ok(()) => Vec::new(),
+        Err(KeyGenError::Timeout(missing)) if !inbox.accusations.is_empty() => missing,
+        Err(e) => return Err(e),
+    };
+    let mut accusations = inbox.accusations.into_iter();
+    let Some(first) = accusations.next() else {
+        return Ok(blame_machine.complete());
+    };
+    let (blame_machine, faulty) = blame_machine.blame(first.sender, first.recipient, first.share, first.proof);
+    let mut faulty = vec![faulty];
+    for accusation in accusations {
+        faulty.push(blame_machine.blame(accusation.sender, accusation.recipient, accusation.share, accusation.proof));
+    }
+    faulty.sort_unstable();
+    faulty.dedup();
+    Err(KeyGenError::Faulty { faulty, unresponsive })
+}
+
+// What the other participants sent so far. A fast peer can be a round
+// ahead of us, so everything is kept from the start.
+struct Inbox {
+    round_timeout: Duration,
+    commitments: HashMap<u16,EncryptionKeyMessage<Ed25519,Commitments<Ed25519>>>,
+    shares: HashMap<u16,EncryptedMessage<Ed25519,EdSecretShare>>,
+    confirmations: HashSet<u16>,
+    accusations: Vec<Accusation>,
+}
+
+impl Inbox {
+    fn new(round_timeout: Duration) -> Self {
+        Inbox {
+            round_timeout,
+            commitments: HashMap::new(),
+            shares: HashMap::new(),
+            confirmations: HashSet::new(),
+            accusations: Vec::new(),
         }
     }
-    let (key_machine, shares) = secret_share_machine.generate_secret_shares(&mut OsRng, commitments_map).unwrap();
 
-    send_channel.send(KeyGenThreadOutput::SecretShares(shares));
+    // Receives until `received` holds for every one of `others`, for at most
+    // a round.
+    fn wait_for(&mut self, receive_channel: &Receiver<KeyGenThreadMessage>, others: &[u16],
+        received: impl Fn(&Inbox, u16) -> bool
+    ) -> Result<(), KeyGenError> {
+        let deadline = Instant::now() + self.round_timeout;
+        while !others.iter().all(|i| received(self, *i)) {
+            match receive_channel.recv_deadline(deadline) {
+                Ok(KeyGenThreadMessage::Commitment((index, commitment))) => {
+                    self.commitments.insert(index, commitment);
+                },
+                Ok(KeyGenThreadMessage::SecretShare((index, share))) => {
+                    self.shares.insert(index, share);
+                },
+                Ok(KeyGenThreadMessage::Confirmation(index)) => {
+                    self.confirmations.insert(index);
+                },
+                Ok(KeyGenThreadMessage::Accusation(accusation)) => self.accusations.push(accusation),
+                Err(RecvTimeoutError::Timeout) => {
+                    let missing = others.iter().filter(|i| !received(self, **i)).cloned().collect();
+                    return Err(KeyGenError::Timeout(missing));
+                },
+                Err(RecvTimeoutError::Disconnected) => return Err(KeyGenError::Cancelled),
+            }
+        }
+        Ok(())
+    }
+}
 
-    let mut shares_map = HashMap::<u16,EncryptedMessage<_,_>>::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc::{self, UnboundedReceiver};
+    use modular_frost::ThresholdKeys;
 
-    loop {
-        let (index, share) = match receive_channel.recv().unwrap(){
-            KeyGenThreadMessage::SecretShare((index,share)) => (index,share),
-            _ => {panic!("invalid msg")}
-        };
-        shares_map.insert(index, share);
-        if shares_map.len() == (n -1) as usize{
-            break;
+    // Runs a DKG between `n` participants in this thread, passing their
+    // messages like the behaviour does. `bad_shares` are (sender, recipient)
+    // pairs whose share gets corrupted, `unconfirmed` never confirm.
+    fn key_gen(t: u16, n: u16, round_timeout: Duration, bad_shares: &[(u16, u16)], unconfirmed: &[u16]
+    ) -> HashMap<u16, Result<ThresholdCore<Ed25519>, KeyGenError>> {
+        let (sessions, mut outputs): (Vec<KeyGenSession>, Vec<UnboundedReceiver<KeyGenThreadOutput>>) = (1..=n)
+            .map(|i| {
+                let (sender, output) = mpsc::unbounded();
+                let params = ThresholdParams::new(t, n, i).unwrap();
+                (KeyGenSession::spawn("test".to_string(), params, round_timeout, sender), output)
+            })
+            .unzip();
+        let others = |i: u16| (1..=n).filter(move |j| *j != i);
+        let mut results = HashMap::new();
+        while results.len() < n as usize {
+            let mut idle = true;
+            for i in 1..=n {
+                let Ok(Some(output)) = outputs[i as usize - 1].try_next() else {
+                    continue;
+                };
+                idle = false;
+                match output {
+                    KeyGenThreadOutput::Commitments(commitments) => {
+                        for j in others(i) {
+                            sessions[j as usize - 1].handle_commitments(i, &commitments.serialize()).unwrap();
+                        }
+                    },
+                    KeyGenThreadOutput::SecretShares(shares) => {
+                        for (j, share) in shares {
+                            let mut data = share.serialize();
+                            if bad_shares.contains(&(i, j)) {
+                                let at = data.len() - 32;
+                                data[at] ^= 1;
+                            }
+                            sessions[j as usize - 1].handle_share(i, &data).unwrap();
+                        }
+                    },
+                    KeyGenThreadOutput::Confirmation if unconfirmed.contains(&i) => {},
+                    KeyGenThreadOutput::Confirmation => {
+                        for j in others(i) {
+                            sessions[j as usize - 1].handle_confirmation(i);
+                        }
+                    },
+                    KeyGenThreadOutput::Accusation { participant, share, proof } => {
+                        for j in others(i) {
+                            sessions[j as usize - 1].handle_accusation(i, participant, &share, proof.as_deref()).unwrap();
+                        }
+                    },
+                    KeyGenThreadOutput::Key(core) => {
+                        results.insert(i, Ok(core));
+                    },
+                    KeyGenThreadOutput::Failed(error) => {
+                        results.insert(i, Err(error));
+                    },
+                }
+            }
+            if idle {
+                thread::sleep(Duration::from_millis(1));
+            }
         }
+        results
+    }
+
+    fn faults(result: &Result<ThresholdCore<Ed25519>, KeyGenError>) -> (Vec<u16>, Vec<u16>) {
+        let Err(error) = result else {
+            panic!("key generation should fail");
+        };
+        (error.faulty_participants().to_vec(), error.unresponsive_participants().to_vec())
+    }
+
+    #[test]
+    fn start_rejects_invalid_params() {
+        let (sender, _output) = mpsc::unbounded();
+        assert!(matches!(KeyGenSession::start("test".to_string(), 3, 2, 1, sender), Err(KeyGenError::InvalidParams)));
+    }
+
+    #[test]
+    fn inbox_times_out_on_whoever_is_missing() {
+        let (sender, receiver) = unbounded();
+        sender.send(KeyGenThreadMessage::Confirmation(3)).unwrap();
+        let mut inbox = Inbox::new(Duration::from_millis(20));
+        let result = inbox.wait_for(&receiver, &[2, 3, 4], |inbox, i| inbox.confirmations.contains(&i));
+        assert!(matches!(result, Err(KeyGenError::Timeout(missing)) if missing == vec![2, 4]));
     }
-    
-    let blame_machine = key_machine.calculate_share(&mut OsRng, shares).unwrap();
-    let key = blame_machine.complete();
-    send_channel.send(KeyGenThreadOutput::Key(key));
-    
-}
\ No newline at end of file
+
+    #[test]
+    fn inbox_notices_a_cancelled_run() {
+        let (sender, receiver) = unbounded();
+        drop(sender);
+        let mut inbox = Inbox::new(ROUND_TIMEOUT);
+        let result = inbox.wait_for(&receiver, &[2], |inbox, i| inbox.confirmations.contains(&i));
+        assert!(matches!(result, Err(KeyGenError::Cancelled)));
+    }
+
+    #[test]
+    fn key_gen_agrees_on_a_key() {
+        let results = key_gen(2, 3, ROUND_TIMEOUT, &[], &[]);
+        let keys: HashSet<_> = results.values()
+            .map(|result| match result {
+                Ok(core) => ThresholdKeys::new(core.clone()).group_key().compress().to_bytes(),
+                Err(e) => panic!("key generation failed: {}", e),
+            })
+            .collect();
+        assert_eq!(keys.len(), 1);
+    }
+
+    #[test]
+    fn every_accusation_is_blamed() {
+        // 1 and 2 each send one bad share, to 3 and 4
+        let results = key_gen(2, 4, ROUND_TIMEOUT, &[(1, 3), (2, 4)], &[]);
+        assert_eq!(faults(&results[&1]), (vec![1, 2], vec![]));
+        assert_eq!(faults(&results[&2]), (vec![1, 2], vec![]));
+        // the accusers stop at what they saw themselves
+        assert_eq!(faults(&results[&3]), (vec![1], vec![]));
+        assert_eq!(faults(&results[&4]), (vec![2], vec![]));
+    }
+
+    #[test]
+    fn accusations_are_blamed_after_a_timeout() {
+        let results = key_gen(2, 4, Duration::from_secs(3), &[(1, 3)], &[4]);
+        assert_eq!(faults(&results[&2]), (vec![1], vec![4]));
+    }
+}